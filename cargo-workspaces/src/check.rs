@@ -0,0 +1,20 @@
+use crate::utils::{check_publishable, info, RegistryOpt, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Check that the crates are ready to be published
+#[derive(Debug, Parser)]
+pub struct Check {
+    #[clap(flatten)]
+    registry: RegistryOpt,
+}
+
+impl Check {
+    pub fn run(self, metadata: Metadata) -> Result {
+        check_publishable(&metadata, self.registry.registry.as_ref(), false)?;
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
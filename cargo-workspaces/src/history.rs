@@ -0,0 +1,130 @@
+use crate::utils::{count_commits, get_pkgs, list_tags, Error, Result, Tag, TagOpt};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use oclif::{console::style, term::TERM_OUT};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+/// List the released versions of crates from git tags
+#[derive(Debug, Parser)]
+pub struct History {
+    /// Show the history of only this crate
+    #[clap(value_name = "CRATE")]
+    name: Option<String>,
+
+    /// Show information as a JSON array
+    #[clap(long)]
+    json: bool,
+
+    #[clap(flatten)]
+    tag: TagOpt,
+}
+
+#[derive(Serialize, Debug)]
+struct Release {
+    #[serde(flatten)]
+    tag: Tag,
+    commits: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct CrateHistory {
+    name: String,
+    releases: Vec<Release>,
+}
+
+impl History {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let mut pkgs = get_pkgs(&metadata, self.name.is_some())?;
+
+        if let Some(name) = &self.name {
+            pkgs.retain(|p| &p.name == name);
+
+            if pkgs.is_empty() {
+                return Err(Error::PackageNotFound { id: name.clone() });
+            }
+        }
+
+        let tags = list_tags(&metadata.workspace_root)?;
+        let mut histories = vec![];
+
+        for pkg in pkgs {
+            let path = if pkg.path.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                pkg.path.to_string_lossy().to_string()
+            };
+
+            let mut releases = vec![];
+            let mut previous: Option<String> = None;
+
+            for tag in self
+                .tag
+                .releases(&tags, &pkg.name, !pkg.config.independent.unwrap_or(false))
+            {
+                let range =
+                    previous.map_or(tag.commit.clone(), |p| format!("{}..{}", p, tag.commit));
+                let commits = count_commits(&metadata.workspace_root, &range, &path)?;
+
+                previous = Some(tag.commit.clone());
+                releases.push(Release { tag, commits });
+            }
+
+            histories.push(CrateHistory {
+                name: pkg.name,
+                releases,
+            });
+        }
+
+        if self.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&histories)?)?);
+        }
+
+        for history in histories {
+            TERM_OUT.write_line(&history.name)?;
+
+            if history.releases.is_empty() {
+                TERM_OUT.write_line(&format!("  {}", style("no releases").black().bright()))?;
+                continue;
+            }
+
+            let first = history
+                .releases
+                .iter()
+                .map(|x| x.tag.version.to_string().len() + 1)
+                .max()
+                .unwrap_or(0);
+            let second = history
+                .releases
+                .iter()
+                .map(|x| x.tag.name.len())
+                .max()
+                .unwrap_or(0);
+
+            // Newest release first
+            for release in history.releases.iter().rev() {
+                TERM_OUT.write_line(&format!(
+                    "  {}{:f$} {}{:s$} {} {} {}",
+                    style(format!("v{}", release.tag.version)).green(),
+                    "",
+                    style(&release.tag.name).cyan(),
+                    "",
+                    release.tag.date,
+                    style(&release.tag.commit[..release.tag.commit.len().min(7)]).yellow(),
+                    style(format!(
+                        "{} commit{}",
+                        release.commits,
+                        if release.commits == 1 { "" } else { "s" }
+                    ))
+                    .black()
+                    .bright(),
+                    f = first - release.tag.version.to_string().len() - 1,
+                    s = second - release.tag.name.len(),
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+}
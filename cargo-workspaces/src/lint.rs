@@ -0,0 +1,27 @@
+use crate::utils::{info, is_private, lint, report_lints, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Check the crate manifests for common mistakes
+#[derive(Debug, Parser)]
+pub struct Lint {
+    /// Fail on warnings too
+    #[clap(long)]
+    strict: bool,
+}
+
+impl Lint {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let pkgs = metadata
+            .packages
+            .iter()
+            .filter(|p| !is_private(p))
+            .collect::<Vec<_>>();
+
+        report_lints(&lint(&metadata, &pkgs)?, self.strict)?;
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
@@ -1,16 +1,25 @@
 mod changed;
+mod check;
 mod create;
 mod exec;
+mod history;
 mod init;
+mod lint;
 mod list;
+mod owner;
+mod package;
 mod plan;
 mod publish;
 mod rename;
+mod restore;
+mod status;
+mod tag;
 mod version;
+mod yank;
 
 mod utils;
 
-use cargo_metadata::{CargoOpt, MetadataCommand};
+use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
 use clap::Parser;
 use oclif::finish;
 
@@ -26,6 +35,15 @@ enum Subcommand {
     Rename(rename::Rename),
     Init(init::Init),
     Plan(plan::Plan),
+    Status(status::Status),
+    History(history::History),
+    Check(check::Check),
+    Lint(lint::Lint),
+    Yank(yank::Yank),
+    Owner(owner::Owner),
+    Package(package::Package),
+    Restore(restore::Restore),
+    Tag(tag::Tag),
 }
 
 #[derive(Debug, Parser)]
@@ -75,7 +93,17 @@ fn main() {
             cmd.manifest_path(path);
         }
 
-        let metadata = cmd.exec().unwrap();
+        let mut metadata = cmd.exec().unwrap();
+
+        // `restore` reports the manifests it restores by itself
+        if !matches!(opt.subcommand, Subcommand::Restore(_)) {
+            match recover(&metadata) {
+                // The metadata was read from the modified manifests
+                Ok(true) => metadata = cmd.exec().unwrap(),
+                Ok(false) => {}
+                Err(err) => finish(Err(err)),
+            }
+        }
 
         match opt.subcommand {
             Subcommand::List(x) => x.run(metadata),
@@ -86,6 +114,15 @@ fn main() {
             Subcommand::Create(x) => x.run(metadata),
             Subcommand::Rename(x) => x.run(metadata),
             Subcommand::Plan(x) => x.run(metadata),
+            Subcommand::Status(x) => x.run(metadata),
+            Subcommand::History(x) => x.run(metadata),
+            Subcommand::Check(x) => x.run(metadata),
+            Subcommand::Lint(x) => x.run(metadata),
+            Subcommand::Yank(x) => x.run(metadata),
+            Subcommand::Owner(x) => x.run(metadata),
+            Subcommand::Package(x) => x.run(metadata),
+            Subcommand::Restore(x) => x.run(metadata),
+            Subcommand::Tag(x) => x.run(metadata),
             _ => unreachable!(),
         }
     };
@@ -93,9 +130,23 @@ fn main() {
     finish(result)
 }
 
+/// Restores the manifests left without dev-dependencies by an interrupted run.
+/// Returns whether any manifest was restored.
+fn recover(metadata: &Metadata) -> utils::Result<bool> {
+    let restored = utils::restore_dev_deps(&utils::journal_dir(metadata)?)?;
+
+    for manifest in &restored {
+        utils::warn!("restored dev-dependencies", manifest.display());
+    }
+
+    Ok(!restored.is_empty())
+}
+
 fn set_handlers() {
     // https://github.com/console-rs/dialoguer/issues/77
     ctrlc::set_handler(move || {
+        utils::restore_active_dev_deps();
+
         let term = dialoguer::console::Term::stdout();
         let _ = term.show_cursor();
         // Mimic normal `Ctrl-C` exit code.
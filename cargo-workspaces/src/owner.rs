@@ -0,0 +1,205 @@
+use crate::utils::{cargo, info, is_private, warn, Error, Indexes, RegistryOpt, Result};
+
+use cargo_metadata::{Metadata, Package};
+use clap::{ArgGroup, Parser};
+use globset::Glob;
+use oclif::{console::style, term::TERM_OUT};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+/// Manage the owners of the crates on the registry
+#[derive(Debug, Parser)]
+#[clap(group(ArgGroup::new("action").required(true).args(&["add", "remove", "list"])))]
+pub struct Owner {
+    /// Invite the user or team as an owner of the crates
+    #[clap(short, long, value_name = "LOGIN")]
+    add: Option<String>,
+
+    /// Remove the user or team from the owners of the crates
+    #[clap(short, long, value_name = "LOGIN")]
+    remove: Option<String>,
+
+    /// List the owners of the crates
+    #[clap(short, long)]
+    list: bool,
+
+    /// Ignore the crates matched by glob
+    #[clap(long, value_name = "PATTERN")]
+    ignore: Option<String>,
+
+    /// Show information as a JSON array
+    #[clap(long)]
+    json: bool,
+
+    #[clap(flatten)]
+    registry: RegistryOpt,
+}
+
+#[derive(Serialize, Debug)]
+struct CrateOwners {
+    name: String,
+    owners: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+}
+
+impl Owner {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let ignore = self
+            .ignore
+            .as_ref()
+            .map(|x| Glob::new(x).map(|x| x.compile_matcher()))
+            .transpose()?;
+
+        let mut indexes = Indexes::new(
+            &metadata,
+            self.registry.registry.as_ref(),
+            false,
+            self.registry.token.as_ref(),
+        );
+
+        let mut pkgs = vec![];
+
+        for pkg in &metadata.packages {
+            if is_private(pkg) || ignore.as_ref().is_some_and(|x| x.is_match(&pkg.name)) {
+                continue;
+            }
+
+            let registry = indexes.registries(pkg).remove(0);
+
+            // Crates that were never published have no owners yet
+            if !indexes.has_crate(&registry, pkg)? {
+                warn!("not published", pkg.name);
+                continue;
+            }
+
+            pkgs.push((pkg, registry));
+        }
+
+        // Look up all the owners before changing any, so that a failure doesn't
+        // leave the crates half updated
+        let mut crates = vec![];
+
+        for (pkg, registry) in &pkgs {
+            crates.push(CrateOwners {
+                name: pkg.name.clone(),
+                owners: self.owners(&metadata, pkg, registry)?,
+                changed: None,
+            });
+        }
+
+        for ((pkg, registry), krate) in pkgs.iter().zip(&mut crates) {
+            let owners = &mut krate.owners;
+
+            krate.changed = if let Some(login) = &self.add {
+                if owners.contains(login) {
+                    info!("already owner", format!("{} of {}", login, pkg.name));
+                    Some(false)
+                } else {
+                    self.modify(&metadata, pkg, registry, "--add", login)?;
+                    info!("added", format!("{} to {}", login, pkg.name));
+                    owners.push(login.clone());
+                    Some(true)
+                }
+            } else if let Some(login) = &self.remove {
+                if owners.contains(login) {
+                    self.modify(&metadata, pkg, registry, "--remove", login)?;
+                    info!("removed", format!("{} from {}", login, pkg.name));
+                    owners.retain(|x| x != login);
+                    Some(true)
+                } else {
+                    info!("not owner", format!("{} of {}", login, pkg.name));
+                    Some(false)
+                }
+            } else {
+                None
+            };
+        }
+
+        if self.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&crates)?)?);
+        }
+
+        if self.list {
+            let first = crates.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+            for krate in crates {
+                TERM_OUT.write_line(&format!(
+                    "{}{:f$} {}",
+                    krate.name,
+                    "",
+                    style(krate.owners.join(", ")).cyan(),
+                    f = first - krate.name.len(),
+                ))?;
+            }
+
+            return Ok(());
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+
+    /// Returns the logins of the crate owners
+    fn owners(
+        &self,
+        metadata: &Metadata,
+        pkg: &Package,
+        registry: &Option<String>,
+    ) -> Result<Vec<String>> {
+        let (stdout, stderr) = self.cargo_owner(metadata, pkg, registry, &["--list"])?;
+
+        if stderr.contains("error:") {
+            return Err(Error::Owner(pkg.name.clone()));
+        }
+
+        // Each line looks like `login (Name)`
+        Ok(stdout
+            .lines()
+            .filter_map(|x| x.split_whitespace().next())
+            .map(|x| x.to_string())
+            .collect())
+    }
+
+    fn modify(
+        &self,
+        metadata: &Metadata,
+        pkg: &Package,
+        registry: &Option<String>,
+        action: &str,
+        login: &str,
+    ) -> Result {
+        let (_, stderr) = self.cargo_owner(metadata, pkg, registry, &[action, login])?;
+
+        if stderr.contains("error:") {
+            return Err(Error::Owner(pkg.name.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn cargo_owner(
+        &self,
+        metadata: &Metadata,
+        pkg: &Package,
+        registry: &Option<String>,
+        action: &[&str],
+    ) -> Result<(String, String)> {
+        let mut args = vec!["owner"];
+        args.extend_from_slice(action);
+
+        if let Some(registry) = registry {
+            args.push("--registry");
+            args.push(registry);
+        }
+
+        if let Some(ref token) = self.registry.token {
+            args.push("--token");
+            args.push(token);
+        }
+
+        args.push(&pkg.name);
+
+        cargo(&metadata.workspace_root, &args, &[])
+    }
+}
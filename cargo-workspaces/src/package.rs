@@ -0,0 +1,89 @@
+use std::fs::{copy, create_dir_all};
+
+use crate::utils::{
+    dag, filter_private, info, package_all, write_index_entry, BuildOpt, DevDepsMode, Indexes,
+    Result, INTERNAL_ERR,
+};
+
+use camino::Utf8PathBuf;
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Package the crates into a directory
+#[derive(Debug, Parser)]
+pub struct Package {
+    /// Directory to copy the `.crate` files to
+    #[clap(long, value_name = "DIR")]
+    out_dir: Utf8PathBuf,
+
+    /// Also write a local registry index for the crates in the directory
+    #[clap(long)]
+    index: bool,
+
+    /// Don't remove dev-dependencies while packaging
+    #[clap(long)]
+    no_remove_dev_deps: bool,
+
+    /// Only strip the versions of dev-dependencies on workspace crates instead of removing all dev-dependencies
+    #[clap(long, conflicts_with = "no-remove-dev-deps")]
+    only_workspace_dev_deps: bool,
+
+    /// The Cargo registry to use
+    #[clap(long, forbid_empty_values(true))]
+    registry: Option<String>,
+
+    #[clap(flatten)]
+    build: BuildOpt,
+}
+
+impl Package {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let pkgs = metadata
+            .packages
+            .iter()
+            .map(|x| (x.clone(), x.version.to_string()))
+            .collect::<Vec<_>>();
+
+        let (_, visited) = dag(&pkgs);
+
+        // Filter out private packages
+        let visited = filter_private(visited, &pkgs);
+
+        self.build.check_features(
+            pkgs.iter()
+                .filter(|(pkg, _)| visited.contains(&pkg.manifest_path))
+                .map(|(pkg, _)| pkg),
+        )?;
+
+        let indexes = Indexes::new(&metadata, self.registry.as_ref(), false, None);
+
+        let packaged = package_all(
+            &metadata,
+            &pkgs,
+            &visited,
+            &indexes,
+            &metadata.target_directory.join("ws-package"),
+            DevDepsMode::new(self.no_remove_dev_deps, self.only_workspace_dev_deps),
+            &self.build,
+        )?;
+
+        create_dir_all(&self.out_dir)?;
+
+        for krate in &packaged {
+            copy(
+                &krate.file,
+                self.out_dir
+                    .join(krate.file.file_name().expect(INTERNAL_ERR)),
+            )?;
+
+            if self.index {
+                write_index_entry(&self.out_dir.join("index"), krate)?;
+            }
+
+            info!("packaged", format!("{} v{}", krate.pkg.name, krate.version));
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
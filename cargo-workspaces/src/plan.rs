@@ -1,6 +1,6 @@
 use crate::utils::{
-    create_http_client, dag, filter_private, get_pkgs, is_published, list, package_registry,
-    ListOpt, ListPublicOpt, RegistryOpt, Result, INTERNAL_ERR,
+    dag, filter_private, get_pkgs, list, Indexes, ListOpt, ListPublicOpt, RegistryOpt, Result,
+    INTERNAL_ERR,
 };
 
 use cargo_metadata::Metadata;
@@ -30,17 +30,30 @@ impl Plan {
 
         let (names, visited) = dag(&pkgs);
 
-        let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
+        let visited = filter_private(visited, &pkgs);
+        let mut indexes = Indexes::new(
+            &metadata,
+            self.registry.registry.as_ref(),
+            false,
+            self.registry.token.as_ref(),
+        );
 
-        let pkg_ids = filter_private(visited, &pkgs)
+        if self.skip_published {
+            indexes.prefetch(
+                &visited
+                    .iter()
+                    .map(|p| names.get(p).expect(INTERNAL_ERR).0)
+                    .collect::<Vec<_>>(),
+            )?;
+        }
+
+        let pkg_ids = visited
             .into_iter()
             .map(|p| {
                 let (pkg, version) = names.get(&p).expect(INTERNAL_ERR);
 
                 let published = if self.skip_published {
-                    let index_url =
-                        package_registry(&metadata, self.registry.registry.as_ref(), pkg)?;
-                    is_published(&http_client, index_url, &pkg.name, version)?
+                    indexes.is_published(pkg, version)?
                 } else {
                     false
                 };
@@ -1,14 +1,24 @@
-use std::{thread, time::Duration};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet},
+    thread,
+    time::Duration,
+};
 
 use crate::utils::{
-    basic_checks, cargo, create_http_client, dag, filter_private, info, is_published,
-    package_registry, should_remove_dev_deps, warn, DevDependencyRemover, Error, RegistryOpt,
-    Result, VersionOpt, INTERNAL_ERR,
+    cargo, check_publishable, dag, filter_private, git, info, is_private, journal_dir, lint,
+    list_tags, package_all, publish_failure, report_lints, run_package_hook, run_workspace_hook,
+    should_remove_dev_deps, warn, BuildOpt, DevDependencyRemover, DevDepsMode, Error, Hook,
+    HookEnv, Indexes, PublishFailure, RegistryOpt, Result, VersionOpt, Worktree, INTERNAL_ERR,
 };
 
 use camino::Utf8PathBuf;
-use cargo_metadata::Metadata;
+use cargo_metadata::{CargoOpt, DependencyKind, Metadata, MetadataCommand, Package};
 use clap::Parser;
+use globset::Glob;
+use indexmap::IndexSet as Set;
+use semver::Version;
+
+const RETRY_DELAY: Duration = Duration::from_secs(10);
 
 /// Publish crates in the project
 #[derive(Debug, Parser)]
@@ -22,6 +32,10 @@ pub struct Publish {
     #[clap(long, alias = "from-git")]
     publish_as_is: bool,
 
+    /// Publish crates from the commit of this git tag without versioning, leaving the working tree untouched
+    #[clap(long, value_name = "TAG", conflicts_with = "publish-as-is")]
+    from_tag: Option<String>,
+
     /// Skip already published crate versions
     #[clap(long, hide = true)]
     skip_published: bool,
@@ -30,6 +44,10 @@ pub struct Publish {
     #[clap(long)]
     no_verify: bool,
 
+    /// Package and verify all the crates against each other's packaged sources before publishing any
+    #[clap(long, conflicts_with = "no-verify")]
+    verify_local: bool,
+
     /// Allow dirty working directories to be published
     #[clap(long)]
     allow_dirty: bool,
@@ -38,13 +56,28 @@ pub struct Publish {
     #[clap(long)]
     no_remove_dev_deps: bool,
 
+    /// Only strip the versions of dev-dependencies on workspace crates instead of removing all dev-dependencies
+    #[clap(long, conflicts_with = "no-remove-dev-deps")]
+    only_workspace_dev_deps: bool,
+
     /// Runs in dry-run mode
     #[clap(long)]
     dry_run: bool,
 
+    /// Only publish the crates matched by glob and the unpublished crates they depend on (can be repeated)
+    #[clap(long, value_name = "PATTERN", multiple_occurrences = true)]
+    package: Vec<String>,
+
     #[clap(flatten)]
     registry: RegistryOpt,
 
+    #[clap(flatten)]
+    build: BuildOpt,
+
+    /// Publish each crate to every registry in its `publish` list
+    #[clap(long, conflicts_with_all = &["registry", "token"])]
+    all_registries: bool,
+
     /// Assert that `Cargo.lock` will remain unchanged
     #[clap(long)]
     locked: bool,
@@ -52,10 +85,31 @@ pub struct Publish {
     /// Number of seconds to wait between publish attempts
     #[clap(long, value_name = "SECONDS")]
     publish_interval: Option<u64>,
+
+    /// Number of times to retry publishing a crate when rate limited or timed out
+    #[clap(long, default_value = "3", value_name = "COUNT")]
+    publish_retries: u32,
 }
 
 impl Publish {
     pub fn run(mut self, metadata: Metadata) -> Result {
+        if let Some(tag) = self.from_tag.take() {
+            return self.run_from_tag(&metadata, &tag);
+        }
+
+        if !self.package.is_empty() && !self.publish_as_is && !self.dry_run {
+            return Err(Error::PackageWithVersioning);
+        }
+
+        self.build
+            .check_features(metadata.packages.iter().filter(|x| !is_private(x)))?;
+
+        check_publishable(
+            &metadata,
+            self.registry.registry.as_ref(),
+            self.all_registries,
+        )?;
+
         if self.dry_run {
             warn!(
                 "Dry run doesn't check that all dependencies have been published.",
@@ -68,22 +122,29 @@ impl Publish {
             }
         }
 
+        let mut indexes = Indexes::new(
+            &metadata,
+            self.registry.registry.as_ref(),
+            self.all_registries,
+            self.registry.token.as_ref(),
+        );
+
         let pkgs = if !self.publish_as_is {
-            self.version
-                .do_versioning(&metadata)?
-                .iter()
-                .map(|x| {
-                    (
-                        metadata
-                            .packages
-                            .iter()
-                            .find(|y| x.0 == &y.name)
-                            .expect(INTERNAL_ERR)
-                            .clone(),
-                        x.1.to_string(),
-                    )
-                })
-                .collect::<Vec<_>>()
+            // Nothing is committed or pushed yet if the checks fail
+            let new_versions = self.version.do_versioning(&metadata, |new_versions| {
+                self.check_lints(&metadata, new_versions.keys().map(|x| x.as_str()))?;
+
+                if self.verify_local {
+                    let pkgs = versioned_pkgs(&metadata, new_versions);
+                    let (_, visited) = dag(&pkgs);
+
+                    self.verify_local(&metadata, &pkgs, &filter_private(visited, &pkgs), &indexes)?;
+                }
+
+                Ok(())
+            })?;
+
+            versioned_pkgs(&metadata, &new_versions)
         } else {
             metadata
                 .packages
@@ -97,106 +158,394 @@ impl Publish {
         // Filter out private packages
         let visited = filter_private(visited, &pkgs);
 
-        let http_client = create_http_client(&metadata.workspace_root, &self.registry.token)?;
+        indexes.prefetch(
+            &visited
+                .iter()
+                .map(|p| names.get(p).expect(INTERNAL_ERR).0)
+                .collect::<Vec<_>>(),
+        )?;
+
+        let visited = if self.package.is_empty() {
+            visited
+        } else {
+            let visited = self.closure(&pkgs, visited, &mut indexes)?;
+
+            self.build
+                .check_features(visited.iter().map(|p| names.get(p).expect(INTERNAL_ERR).0))?;
+
+            visited
+        };
+
+        // The crates were already checked before the version commit otherwise
+        if self.publish_as_is {
+            self.check_lints(
+                &metadata,
+                visited
+                    .iter()
+                    .map(|p| names.get(p).expect(INTERNAL_ERR).0.name.as_str()),
+            )?;
+
+            if self.verify_local {
+                self.verify_local(&metadata, &pkgs, &visited, &indexes)?;
+            }
+        }
+
+        let versions = visited
+            .iter()
+            .map(|p| names.get(p).expect(INTERNAL_ERR))
+            .map(|(pkg, version)| (pkg.name.as_str(), version.as_str()))
+            .collect::<Vec<_>>();
+
+        // The crates share a version when it's a fixed release
+        let common_version = match versions.first() {
+            Some((_, first)) if versions.iter().all(|(_, v)| v == first) => Some(*first),
+            _ => None,
+        };
+
+        let workspace_env = HookEnv {
+            new_version: common_version.map(|x| x.to_string()),
+            tag: common_version
+                .and_then(|x| Version::parse(x).ok())
+                .map(|x| self.version.git.tag.global_tag(&x)),
+            versions: versions
+                .iter()
+                .map(|(name, version)| format!("{}@{}", name, version))
+                .collect(),
+            ..Default::default()
+        };
+
+        if !self.dry_run {
+            run_workspace_hook(&metadata, Hook::PrePublish, &workspace_env)?;
+        }
+
+        let mut any_uploaded = false;
 
         for p in &visited {
             let (pkg, version) = names.get(p).expect(INTERNAL_ERR);
             let name = pkg.name.clone();
 
+            let env = HookEnv {
+                name: Some(name.clone()),
+                new_version: Some(version.to_string()),
+                tag: Version::parse(version)
+                    .ok()
+                    .map(|x| self.version.git.tag.individual_tag(&name, &x)),
+                versions: workspace_env.versions.clone(),
+                ..Default::default()
+            };
+
+            let mut prepublished = false;
+            let mut uploaded = false;
+
             if self.dry_run {
                 info!("checking", name);
 
-                if !self.no_verify && !self.build(&metadata.workspace_root, p)? {
+                if !self.no_verify && !self.build(&metadata.workspace_root, p, pkg)? {
                     warn!("build failed", "");
                 }
-
-                basic_checks(pkg)?;
             }
 
-            let mut args = vec!["publish"];
+            for registry in indexes.registries(pkg) {
+                let mut args = vec!["publish"];
 
-            let name_ver = format!("{} v{}", name, version);
-            let index_url = package_registry(&metadata, self.registry.registry.as_ref(), pkg)?;
+                let name_ver = match &registry {
+                    Some(registry) if self.all_registries => {
+                        format!("{} v{} to {}", name, version, registry)
+                    }
+                    _ => format!("{} v{}", name, version),
+                };
 
-            if is_published(&http_client, index_url, &name, version)? {
-                info!("already published", name_ver);
-                continue;
-            }
+                if indexes.is_published_to(&registry, pkg, version)? {
+                    info!("already published", name_ver);
+                    continue;
+                }
 
-            if self.dry_run {
-                args.push("--dry-run");
-            }
+                if self.dry_run {
+                    args.push("--dry-run");
+                }
 
-            if self.no_verify || self.dry_run {
-                args.push("--no-verify");
-            }
+                if self.no_verify || self.dry_run {
+                    args.push("--no-verify");
+                }
 
-            if self.locked {
-                args.push("--locked");
-            }
+                if self.locked {
+                    args.push("--locked");
+                }
 
-            if let Some(ref registry) = self.registry.registry {
-                args.push("--registry");
-                args.push(registry);
-            }
+                if let Some(ref registry) = registry {
+                    args.push("--registry");
+                    args.push(registry);
+                }
 
-            if let Some(ref token) = self.registry.token {
-                args.push("--token");
-                args.push(token);
-            }
+                if let Some(ref token) = self.registry.token {
+                    args.push("--token");
+                    args.push(token);
+                }
 
-            if let Some(interval) = self.publish_interval {
-                if interval > 0 && !self.dry_run {
-                    info!(
-                        "waiting",
-                        format!("{} seconds before publishing {}", interval, name_ver)
-                    );
-                    thread::sleep(Duration::from_secs(interval));
+                if !self.dry_run && !prepublished {
+                    run_package_hook(pkg, Hook::PrePublish, &env)?;
+                    prepublished = true;
                 }
-            }
 
-            let dev_deps_remover =
-                if self.no_remove_dev_deps || !should_remove_dev_deps(&pkg.dependencies, &pkgs) {
+                if let Some(interval) = self.publish_interval {
+                    if interval > 0 && !self.dry_run {
+                        info!(
+                            "waiting",
+                            format!("{} seconds before publishing {}", interval, name_ver)
+                        );
+                        thread::sleep(Duration::from_secs(interval));
+                    }
+                }
+
+                let dev_deps_mode = self.dev_deps_mode();
+
+                let dev_deps_remover = if dev_deps_mode == DevDepsMode::Keep
+                    || !should_remove_dev_deps(&pkg.dependencies, &pkgs)
+                {
                     None
                 } else {
-                    warn!(
-                        "removing dev-deps since some refer to workspace members with versions",
-                        name_ver
-                    );
-                    Some(DevDependencyRemover::remove_dev_deps(p.as_std_path())?)
+                    if dev_deps_mode == DevDepsMode::StripWorkspace {
+                        warn!(
+                            "stripping versions from dev-deps that refer to workspace members",
+                            name_ver
+                        );
+                    } else {
+                        warn!(
+                            "removing dev-deps since some refer to workspace members with versions",
+                            name_ver
+                        );
+                    }
+
+                    Some(DevDependencyRemover::new(
+                        p.as_std_path(),
+                        &journal_dir(&metadata)?,
+                        metadata.workspace_root.as_std_path(),
+                        dev_deps_mode,
+                        &pkgs,
+                    )?)
                 };
 
-            if dev_deps_remover.is_some() || self.allow_dirty {
-                args.push("--allow-dirty");
+                if dev_deps_remover.is_some() || self.allow_dirty {
+                    args.push("--allow-dirty");
+                }
+
+                let build_args = self.build.args(pkg)?;
+                args.extend(build_args.iter().map(|x| x.as_str()));
+
+                args.push("--manifest-path");
+                args.push(p.as_str());
+
+                let mut retries = 0;
+
+                let failure = loop {
+                    let (_, stderr) = cargo(&metadata.workspace_root, &args, &[])?;
+
+                    match publish_failure(&stderr) {
+                        Some(failure)
+                            if failure.is_transient()
+                                && !self.dry_run
+                                && retries < self.publish_retries =>
+                        {
+                            // Backoff exponentially
+                            let delay = RETRY_DELAY * 2u32.pow(retries);
+                            retries += 1;
+
+                            warn!(
+                                "retrying",
+                                format!(
+                                    "{} in {} seconds ({})",
+                                    name_ver,
+                                    delay.as_secs(),
+                                    failure
+                                )
+                            );
+                            thread::sleep(delay);
+                        }
+                        failure => break failure,
+                    }
+                };
+
+                drop(dev_deps_remover);
+
+                match failure {
+                    None if !self.dry_run => {
+                        info!("published", name_ver);
+                        uploaded = true;
+                    }
+                    None => {}
+                    Some(PublishFailure::AlreadyExists) => info!("already published", name_ver),
+                    Some(failure) if self.dry_run => {
+                        warn!("publish failed", format!("{} ({})", name_ver, failure))
+                    }
+                    Some(failure) => return Err(Error::Publish(name, failure)),
+                }
+            }
+
+            // Not when every registry already had the version
+            if uploaded {
+                run_package_hook(pkg, Hook::PostPublish, &env)?;
+                any_uploaded = true;
             }
+        }
 
-            args.push("--manifest-path");
-            args.push(p.as_str());
+        if any_uploaded {
+            run_workspace_hook(&metadata, Hook::PostPublish, &workspace_env)?;
+        }
 
-            let (_, stderr) = cargo(&metadata.workspace_root, &args, &[])?;
+        info!("success", "ok");
+        Ok(())
+    }
 
-            drop(dev_deps_remover);
+    /// Publishes the crates as they are in a temporary worktree of the tag
+    fn run_from_tag(mut self, metadata: &Metadata, tag: &str) -> Result {
+        if !list_tags(&metadata.workspace_root)?
+            .iter()
+            .any(|t| t.name == tag)
+        {
+            return Err(Error::TagNotFound(tag.to_string()));
+        }
 
-            if !stderr.contains("Uploading") || stderr.contains("error:") {
-                if self.dry_run {
-                    warn!("publish failed", name_ver);
-                } else {
-                    return Err(Error::Publish(name));
-                }
+        // The workspace might not be at the root of the repository
+        let (_, prefix, _) = git(&metadata.workspace_root, &["rev-parse", "--show-prefix"])?;
+
+        let worktree = Worktree::add(
+            &metadata.workspace_root,
+            &metadata.target_directory.join("ws-worktree"),
+            tag,
+        )?;
+
+        info!("checked out", tag);
+
+        let manifest_path = worktree.path.join(prefix).join("Cargo.toml");
+
+        let mut cmd = MetadataCommand::new();
+
+        cmd.features(CargoOpt::AllFeatures);
+        cmd.no_deps();
+        cmd.manifest_path(&manifest_path);
+
+        let tag_metadata = cmd
+            .exec()
+            .map_err(|e| Error::WorktreeMetadata(manifest_path.to_string(), e.to_string()))?;
+
+        self.publish_as_is = true;
+        self.run(tag_metadata)
+    }
+
+    /// Returns the selected crates and the workspace crates they depend on, directly
+    /// or not, that are not published yet, keeping the publishing order
+    fn closure(
+        &self,
+        pkgs: &[(Package, String)],
+        visited: Set<Utf8PathBuf>,
+        indexes: &mut Indexes,
+    ) -> Result<Set<Utf8PathBuf>> {
+        let (names, _) = dag(pkgs);
+        let mut selected = BTreeSet::new();
+
+        for pattern in &self.package {
+            let matcher = Glob::new(pattern)?.compile_matcher();
+            let matched = visited
+                .iter()
+                .filter(|p| matcher.is_match(&names.get(p).expect(INTERNAL_ERR).0.name))
+                .collect::<Vec<_>>();
+
+            if matched.is_empty() {
+                return Err(Error::NoMatchingPackage(pattern.clone()));
             }
 
-            if !self.dry_run {
-                info!("published", name_ver);
+            selected.extend(matched);
+        }
+
+        let mut queue = selected.iter().copied().collect::<Vec<_>>();
+
+        while let Some(p) = queue.pop() {
+            let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+
+            for dep in &pkg.dependencies {
+                if dep.path.is_none() || dep.kind == DependencyKind::Development {
+                    continue;
+                }
+
+                let dep_path = match visited
+                    .iter()
+                    .find(|x| names.get(x).expect(INTERNAL_ERR).0.name == dep.name)
+                {
+                    Some(dep_path) => dep_path,
+                    None => continue,
+                };
+
+                if selected.contains(dep_path) {
+                    continue;
+                }
+
+                let (dep_pkg, version) = names.get(dep_path).expect(INTERNAL_ERR);
+
+                if !indexes.is_published(dep_pkg, version)? {
+                    selected.insert(dep_path);
+                    queue.push(dep_path);
+                }
             }
         }
 
-        info!("success", "ok");
+        Ok(visited
+            .iter()
+            .filter(|p| selected.contains(p))
+            .cloned()
+            .collect())
+    }
+
+    /// Packages the crates in order, verifying each of them against the packaged
+    /// sources of the workspace crates it depends on
+    fn verify_local(
+        &self,
+        metadata: &Metadata,
+        pkgs: &[(Package, String)],
+        visited: &Set<Utf8PathBuf>,
+        indexes: &Indexes,
+    ) -> Result {
+        package_all(
+            metadata,
+            pkgs,
+            visited,
+            indexes,
+            &metadata.target_directory.join("ws-verify"),
+            self.dev_deps_mode(),
+            &self.build,
+        )?;
+
+        info!("verified", "all crates build from their packaged sources");
         Ok(())
     }
 
-    fn build(&self, workspace_root: &Utf8PathBuf, manifest_path: &Utf8PathBuf) -> Result<bool> {
+    /// Reports the lints of the given crates that are public, only denied lints
+    /// stop the publishing
+    fn check_lints<'a>(&self, metadata: &Metadata, names: impl Iterator<Item = &'a str>) -> Result {
+        let names = names.collect::<BTreeSet<_>>();
+        let pkgs = metadata
+            .packages
+            .iter()
+            .filter(|p| !is_private(p) && names.contains(p.name.as_str()))
+            .collect::<Vec<_>>();
+
+        report_lints(&lint(metadata, &pkgs)?, false)
+    }
+
+    fn dev_deps_mode(&self) -> DevDepsMode {
+        DevDepsMode::new(self.no_remove_dev_deps, self.only_workspace_dev_deps)
+    }
+
+    fn build(
+        &self,
+        workspace_root: &Utf8PathBuf,
+        manifest_path: &Utf8PathBuf,
+        pkg: &Package,
+    ) -> Result<bool> {
         let mut args = vec!["build"];
 
+        let build_args = self.build.args(pkg)?;
+        args.extend(build_args.iter().map(|x| x.as_str()));
+
         args.push("--manifest-path");
         args.push(manifest_path.as_str());
 
@@ -209,3 +558,24 @@ impl Publish {
         Ok(true)
     }
 }
+
+/// Returns the packages with their new versions
+fn versioned_pkgs(
+    metadata: &Metadata,
+    new_versions: &Map<String, Version>,
+) -> Vec<(Package, String)> {
+    new_versions
+        .iter()
+        .map(|(name, version)| {
+            (
+                metadata
+                    .packages
+                    .iter()
+                    .find(|x| &x.name == name)
+                    .expect(INTERNAL_ERR)
+                    .clone(),
+                version.to_string(),
+            )
+        })
+        .collect()
+}
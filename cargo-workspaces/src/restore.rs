@@ -0,0 +1,24 @@
+use crate::utils::{info, journal_dir, restore_dev_deps, Result};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+
+/// Restore manifests left without dev-dependencies by an interrupted publish
+#[derive(Debug, Parser)]
+pub struct Restore {}
+
+impl Restore {
+    pub fn run(&self, metadata: Metadata) -> Result {
+        let restored = restore_dev_deps(&journal_dir(&metadata)?)?;
+
+        if restored.is_empty() {
+            info!("nothing to restore", "");
+        }
+
+        for manifest in restored {
+            info!("restored", manifest.display());
+        }
+
+        Ok(())
+    }
+}
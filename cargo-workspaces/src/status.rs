@@ -0,0 +1,165 @@
+use crate::utils::{
+    count_commits, get_pkgs, list_tags, Indexes, RegistryOpt, Result, TagOpt, INTERNAL_ERR,
+};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use oclif::{console::style, term::TERM_OUT};
+use semver::Version;
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+/// Show the release status of the crates
+#[derive(Debug, Parser)]
+pub struct Status {
+    /// Show information as a JSON array
+    #[clap(long)]
+    json: bool,
+
+    #[clap(flatten)]
+    tag: TagOpt,
+
+    #[clap(flatten)]
+    registry: RegistryOpt,
+}
+
+#[derive(Serialize, Debug)]
+struct CrateStatus {
+    name: String,
+    version: Version,
+    tag: Option<String>,
+    commits_since_tag: usize,
+    published: bool,
+    issues: Vec<String>,
+}
+
+impl Status {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let pkgs = get_pkgs(&metadata, false)?;
+        let tags = list_tags(&metadata.workspace_root)?;
+
+        let packages = pkgs
+            .iter()
+            .map(|pkg| {
+                metadata
+                    .packages
+                    .iter()
+                    .find(|x| x.id == pkg.id)
+                    .expect(INTERNAL_ERR)
+            })
+            .collect::<Vec<_>>();
+
+        let mut indexes = Indexes::new(
+            &metadata,
+            self.registry.registry.as_ref(),
+            false,
+            self.registry.token.as_ref(),
+        );
+        indexes.prefetch(&packages)?;
+
+        let mut statuses = vec![];
+
+        for (pkg, package) in pkgs.into_iter().zip(packages) {
+            let releases =
+                self.tag
+                    .releases(&tags, &pkg.name, !pkg.config.independent.unwrap_or(false));
+            let latest = releases.last();
+
+            let path = if pkg.path.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                pkg.path.to_string_lossy().to_string()
+            };
+
+            let range = latest.map_or("HEAD".to_string(), |t| format!("{}..HEAD", t.name));
+            let count = count_commits(&metadata.workspace_root, &range, &path)?;
+
+            let published = indexes.is_published(package, &pkg.version.to_string())?;
+
+            let tagged = releases.iter().any(|t| t.version == pkg.version);
+            let mut issues = vec![];
+
+            if tagged && !published {
+                issues.push("tagged but not published".to_string());
+            }
+
+            if published && !tagged {
+                issues.push("published but no tag".to_string());
+            }
+
+            if let Some(latest) = latest {
+                if latest.version > pkg.version {
+                    issues.push(format!("manifest is behind tag {}", latest.name));
+                }
+            }
+
+            statuses.push(CrateStatus {
+                name: pkg.name,
+                version: pkg.version,
+                tag: latest.map(|t| t.name.clone()),
+                commits_since_tag: count,
+                published,
+                issues,
+            });
+        }
+
+        if self.json {
+            return Ok(TERM_OUT.write_line(&to_string_pretty(&statuses)?)?);
+        }
+
+        let first = statuses.iter().map(|x| x.name.len()).max().unwrap_or(0);
+        let second = statuses
+            .iter()
+            .map(|x| x.version.to_string().len() + 1)
+            .max()
+            .unwrap_or(0);
+        let third = statuses
+            .iter()
+            .map(|x| x.tag.as_ref().map_or(1, |t| t.len()))
+            .max()
+            .unwrap_or(0);
+        let fourth = statuses
+            .iter()
+            .map(|x| x.commits_since_tag.to_string().len() + 1)
+            .max()
+            .unwrap_or(0);
+
+        for status in statuses {
+            let tag = status.tag.unwrap_or_else(|| "-".to_string());
+            let published = if status.published {
+                style("published").green()
+            } else {
+                style("not published").red()
+            };
+
+            TERM_OUT.write_line(
+                format!(
+                    "{}{:f$} {}{:s$} {}{:t$} {}{:u$} {}{}",
+                    status.name,
+                    "",
+                    style(format!("v{}", status.version)).green(),
+                    "",
+                    style(&tag).cyan(),
+                    "",
+                    style(format!("+{}", status.commits_since_tag))
+                        .black()
+                        .bright(),
+                    "",
+                    published,
+                    status
+                        .issues
+                        .iter()
+                        .map(|x| format!(" ({})", style(x).yellow()))
+                        .collect::<String>(),
+                    f = first - status.name.len(),
+                    s = second - status.version.to_string().len() - 1,
+                    t = third - tag.len(),
+                    u = fourth - status.commits_since_tag.to_string().len() - 1,
+                )
+                .trim_end(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
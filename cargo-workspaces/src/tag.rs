@@ -0,0 +1,137 @@
+use crate::utils::{
+    create_tag, existing_tags, get_pkgs, git, info, list_tags, read_config, Error, Result, TagOpt,
+    WorkspaceConfig,
+};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use oclif::term::TERM_OUT;
+
+/// Tag the versions in the manifests that were not released yet
+#[derive(Debug, Parser)]
+pub struct Tag {
+    /// Do not tag individual versions for crates
+    #[clap(long, conflicts_with_all = &["individual-tag-prefix"])]
+    no_individual_tags: bool,
+
+    /// Do not create a global tag for a workspace
+    #[clap(long)]
+    no_global_tag: bool,
+
+    #[clap(flatten)]
+    tag: TagOpt,
+
+    /// Do not push the tags to git remote
+    #[clap(long, conflicts_with_all = &["git-remote"])]
+    no_git_push: bool,
+
+    /// Push the tags to the specified remote
+    #[clap(
+        long,
+        default_value = "origin",
+        value_name = "REMOTE",
+        forbid_empty_values(true)
+    )]
+    git_remote: String,
+}
+
+impl Tag {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+        let pkgs = get_pkgs(&metadata, false)?;
+        let releases = list_tags(&metadata.workspace_root)?;
+        let mut tags = vec![];
+
+        // Same as `version`, the common version is the highest one of the crates that are not independent
+        let common_version = pkgs
+            .iter()
+            .filter(|p| !p.config.independent.unwrap_or(false))
+            .map(|p| &p.version)
+            .max();
+
+        if !self.no_global_tag {
+            if let Some(version) = common_version {
+                tags.push(self.tag.global_tag(version));
+            }
+        }
+
+        if !(self.no_individual_tags || config.no_individual_tags.unwrap_or_default()) {
+            for p in &pkgs {
+                let global = !p.config.independent.unwrap_or(false);
+
+                // The version might have been released by an earlier global tag
+                if self
+                    .tag
+                    .releases(&releases, &p.name, global)
+                    .iter()
+                    .all(|x| x.version != p.version)
+                {
+                    tags.push(self.tag.individual_tag(&p.name, &p.version));
+                }
+            }
+        }
+
+        let remote = if self.no_git_push {
+            None
+        } else {
+            Some(self.git_remote.as_str())
+        };
+
+        let existing = existing_tags(&metadata.workspace_root, remote)?;
+        tags.retain(|x| !existing.contains(x));
+
+        if tags.is_empty() {
+            TERM_OUT.write_line("All versions are already tagged, skipping tagging")?;
+            return Ok(());
+        }
+
+        let mut created = vec![];
+
+        let result = (|| {
+            for tag in &tags {
+                create_tag(&metadata.workspace_root, tag, tag)?;
+                created.push(tag.as_str());
+                info!("tagged", tag);
+            }
+
+            if !self.no_git_push {
+                info!("git", "pushing");
+
+                // Either all the tags make it to the remote or none does
+                let mut args = vec!["push", "--atomic", &self.git_remote];
+                args.extend(tags.iter().map(|x| x.as_str()));
+
+                let pushed = git(&metadata.workspace_root, &args)?;
+
+                if !pushed.0.success() {
+                    return Err(Error::NotPushed(pushed.1, pushed.2));
+                }
+            }
+
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            // Keep going when a tag can't be deleted so that as many as possible are
+            let remains = created
+                .into_iter()
+                .filter_map(
+                    |tag| match git(&metadata.workspace_root, &["tag", "-d", tag]) {
+                        Ok((status, _, _)) if status.success() => None,
+                        Ok((_, _, e)) => Some(format!("tag {} still exists ({})", tag, e)),
+                        Err(e) => Some(format!("tag {} still exists ({})", tag, e)),
+                    },
+                )
+                .collect::<Vec<_>>();
+
+            return Err(if remains.is_empty() {
+                err
+            } else {
+                Error::NotRestored(err.to_string(), remains.join(", "))
+            });
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::utils::{read_config, Error, PackageConfig, Result};
+
+use cargo_metadata::Package;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[clap(next_help_heading = "BUILD OPTIONS")]
+pub struct BuildOpt {
+    /// Space or comma separated list of features to activate for the crates that have them (`crate:feature` for one crate)
+    #[clap(long, value_name = "FEATURES", multiple_occurrences = true)]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[clap(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[clap(long)]
+    pub no_default_features: bool,
+
+    /// Build for the target triple
+    #[clap(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+}
+
+impl BuildOpt {
+    /// Returns the cargo arguments for building the package. The command line
+    /// options take precedence over the ones in the package config.
+    pub fn args(&self, pkg: &Package) -> Result<Vec<String>> {
+        let config: PackageConfig = read_config(&pkg.metadata)?;
+        let mut args = vec![];
+
+        let features = self.features(pkg);
+
+        let features = if features.is_empty() {
+            config.features.unwrap_or_default()
+        } else {
+            features
+        };
+
+        if !features.is_empty() {
+            args.push("--features".to_string());
+            args.push(features.join(","));
+        }
+
+        if self.all_features || config.all_features.unwrap_or_default() {
+            args.push("--all-features".to_string());
+        }
+
+        if self.no_default_features || config.no_default_features.unwrap_or_default() {
+            args.push("--no-default-features".to_string());
+        }
+
+        if let Some(target) = self.target.clone().or(config.target) {
+            args.push("--target".to_string());
+            args.push(target);
+        }
+
+        Ok(args)
+    }
+
+    /// Fails if a feature given on the command line applies to none of the packages
+    pub fn check_features<'a>(&self, pkgs: impl IntoIterator<Item = &'a Package>) -> Result {
+        let pkgs = pkgs.into_iter().collect::<Vec<_>>();
+
+        for feature in self.requested() {
+            if !pkgs.iter().any(|pkg| applies(feature, pkg).is_some()) {
+                return Err(Error::NoMatchingFeature(feature.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the features given on the command line that apply to the package,
+    /// since cargo fails on the features a package doesn't have
+    fn features(&self, pkg: &Package) -> Vec<String> {
+        self.requested().filter_map(|x| applies(x, pkg)).collect()
+    }
+
+    fn requested(&self) -> impl Iterator<Item = &str> {
+        self.features
+            .iter()
+            .flat_map(|x| x.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|x| !x.is_empty())
+    }
+}
+
+/// Returns the feature to pass to cargo for the package if the requested one
+/// applies to it. `crate:feature` is for a single crate, and cargo's own
+/// `dependency/feature` is passed to the crates with that dependency.
+fn applies(feature: &str, pkg: &Package) -> Option<String> {
+    if let Some((name, feature)) = feature.split_once(':') {
+        return (name == pkg.name).then(|| feature.to_string());
+    }
+
+    match feature.split_once('/') {
+        Some((dep, _)) => {
+            let dep = dep.trim_end_matches('?');
+
+            pkg.dependencies
+                .iter()
+                .any(|x| x.rename.as_ref().unwrap_or(&x.name) == dep)
+                .then(|| feature.to_string())
+        }
+        None => pkg
+            .features
+            .contains_key(feature)
+            .then(|| feature.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use cargo_metadata::MetadataCommand;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn test_features() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        write(
+            tempdir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\"]\n",
+        )
+        .unwrap();
+
+        for (name, features) in [
+            ("a", "x = []\nboth = []"),
+            (
+                "b",
+                "y = []\nboth = []\n\n[dependencies]\na = { path = \"../a\" }",
+            ),
+        ] {
+            let dir = tempdir.path().join(name);
+
+            create_dir_all(dir.join("src")).unwrap();
+            write(dir.join("src").join("lib.rs"), "").unwrap();
+            write(
+                dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[features]\n{}\n",
+                    name, features
+                ),
+            )
+            .unwrap();
+        }
+
+        let metadata = MetadataCommand::new()
+            .manifest_path(tempdir.path().join("Cargo.toml"))
+            .no_deps()
+            .exec()
+            .unwrap();
+
+        let pkg = |name: &str| metadata.packages.iter().find(|x| x.name == name).unwrap();
+
+        let build = BuildOpt::parse_from(["build", "--features", "x y,both", "--features", "b:z"]);
+
+        assert_eq!(build.args(pkg("a")).unwrap(), ["--features", "x,both"]);
+        assert_eq!(build.args(pkg("b")).unwrap(), ["--features", "y,both,z"]);
+
+        let build = BuildOpt::parse_from(["build", "--features", "a:x"]);
+
+        assert_eq!(build.args(pkg("a")).unwrap(), ["--features", "x"]);
+        assert!(build.args(pkg("b")).unwrap().is_empty());
+
+        // Cargo's syntax for the feature of a dependency is passed through
+        let build = BuildOpt::parse_from(["build", "--features", "a/x"]);
+
+        assert!(build.args(pkg("a")).unwrap().is_empty());
+        assert_eq!(build.args(pkg("b")).unwrap(), ["--features", "a/x"]);
+        assert!(build.check_features(&metadata.packages).is_ok());
+
+        let build = BuildOpt::parse_from(["build", "--features", "x,unknown"]);
+
+        assert!(matches!(
+            build.check_features(&metadata.packages),
+            Err(Error::NoMatchingFeature(feature)) if feature == "unknown"
+        ));
+
+        let build = BuildOpt::parse_from(["build", "--features", "c:x"]);
+
+        assert!(build.check_features(&metadata.packages).is_err());
+    }
+}
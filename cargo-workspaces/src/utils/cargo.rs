@@ -1,10 +1,11 @@
 use crate::utils::{debug, get_debug, Error, Result, INTERNAL_ERR};
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use lazy_static::lazy_static;
 use oclif::term::TERM_ERR;
 use regex::{Captures, Regex};
 use semver::{Version, VersionReq};
+use toml_edit::{Document, Value};
 
 use std::{
     collections::BTreeMap as Map,
@@ -64,6 +65,24 @@ pub fn cargo<'a>(
     root: &Utf8Path,
     args: &[&'a str],
     env: &[(&'a str, &'a str)],
+) -> Result<(String, String)> {
+    run_cargo(root, args, env, true)
+}
+
+/// Same as [`cargo`] without echoing what cargo prints
+pub fn cargo_quiet<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+) -> Result<(String, String)> {
+    run_cargo(root, args, env, false)
+}
+
+fn run_cargo<'a>(
+    root: &Utf8Path,
+    args: &[&'a str],
+    env: &[(&'a str, &'a str)],
+    echo: bool,
 ) -> Result<(String, String)> {
     debug!("cargo", args.join(" "));
 
@@ -100,7 +119,10 @@ pub fn cargo<'a>(
         for line in BufReader::new(stderr).lines() {
             let line = line?;
 
-            eprintln!("{}", line);
+            if echo {
+                eprintln!("{}", line);
+            }
+
             stderr_lines.push(line);
         }
     }
@@ -150,37 +172,77 @@ pub fn cargo_config_get(root: &Utf8Path, name: &str) -> Result<String> {
     // we're not actually building crates with
     // rustc-stable-masquerading-as-nightly.
 
+    config_get(root, name, false).map(|(value, _)| value)
+}
+
+/// Gets a path from the cargo config. Relative paths are resolved the same way
+/// cargo does, from the parent of the `.cargo` directory of the config file that
+/// defines them, or from the current directory when they come from the
+/// environment or the command line.
+pub fn cargo_config_path(root: &Utf8Path, name: &str) -> Result<Utf8PathBuf> {
+    let (value, origin) = config_get(root, name, true)?;
+
+    let base = origin
+        .as_deref()
+        .map(Utf8Path::new)
+        .filter(|x| x.is_absolute())
+        .and_then(|x| x.parent())
+        .filter(|x| x.file_name() == Some(".cargo"))
+        .and_then(|x| x.parent())
+        .unwrap_or(root);
+
+    Ok(base.join(value))
+}
+
+/// Returns the config value and, if asked for, where it was defined
+fn config_get(root: &Utf8Path, name: &str, show_origin: bool) -> Result<(String, Option<String>)> {
     debug!("cargo config get", name);
 
-    let args = vec!["-Z", "unstable-options", "config", "get", name];
+    let mut args = vec!["-Z", "unstable-options", "config", "get", name];
     let env = &[("RUSTC_BOOTSTRAP", "1")];
 
-    let (stdout, _) = cargo(root, &args, env)?;
+    if show_origin {
+        args.push("--show-origin");
+    }
+
+    // Missing values are expected, so don't show the errors
+    let (stdout, _) = run_cargo(root, &args, env, false)?;
 
     // `cargo config get` returns TOML output, like so:
     //
     //      $ RUSTC_BOOTSTRAP=1 cargo -Z unstable-options config get registries.foobar.index
     //      registries.foobar.index = "https://dl.cloudsmith.io/basic/some-org/foobar/cargo/index.git"
     //
+    // With `--show-origin`, the place where it was defined is added as a comment:
+    //
+    //      registries.foobar.index = "https://..." # /home/user/.cargo/config.toml
+    //
     // The right thing to do is probably to pull in a TOML crate, but since the
     // output is so predictable, and in the interest of keeping dependencies low,
     // we just do some text wrangling instead:
 
-    // tokens is ["registries.foobar.index", "\"some-url\""]
+    // tokens is ["registries.foobar.index", "\"some-url\" # origin"]
     let tokens = stdout
-        .split(" = ")
+        .splitn(2, " = ")
         .map(|x| x.to_string())
         .collect::<Vec<_>>();
 
-    // value is "\"some-url\""
-    let value = tokens.get(1).ok_or(Error::BadConfigGetOutput(stdout))?;
-
-    // we return "some-url"
-    Ok(value
-        .trim()
-        .trim_start_matches('"')
-        .trim_end_matches('"')
-        .into())
+    // value is "\"some-url\" # origin"
+    let value = tokens
+        .get(1)
+        .ok_or_else(|| Error::BadConfigGetOutput(stdout.clone()))?
+        .trim();
+
+    let (value, origin) = match value.strip_prefix('"').and_then(|x| x.split_once('"')) {
+        // we return "some-url" and "origin"
+        Some((value, rest)) => (
+            value.to_string(),
+            rest.trim().strip_prefix('#').map(|x| x.trim().to_string()),
+        ),
+        None => (value.to_string(), None),
+    };
+
+    Ok((value, origin))
 }
 
 #[derive(Debug)]
@@ -402,6 +464,63 @@ pub fn change_versions(
     )
 }
 
+/// Changes the versions of the workspace members in the lockfile the same way
+/// `cargo update -w` would do it
+pub fn change_lockfile(lockfile: String, versions: &Map<String, Version>) -> Result<String> {
+    let mut document = lockfile.parse::<Document>()?;
+
+    if let Some(packages) = document
+        .get_mut("package")
+        .and_then(|x| x.as_array_of_tables_mut())
+    {
+        let mut changed = Map::new();
+
+        for pkg in packages.iter_mut() {
+            // Workspace members don't have a source
+            if pkg.contains_key("source") {
+                continue;
+            }
+
+            let name = pkg.get("name").and_then(|x| x.as_str()).map(String::from);
+
+            if let Some((name, new_version)) = name.and_then(|n| versions.get_key_value(&n)) {
+                if let Some(version) = pkg.get_mut("version").and_then(|x| x.as_value_mut()) {
+                    if let Some(old_version) = version.as_str() {
+                        changed.insert(
+                            format!("{} {}", name, old_version),
+                            format!("{} {}", name, new_version),
+                        );
+                    }
+
+                    replace_value(version, new_version.to_string());
+                }
+            }
+        }
+
+        // Dependencies are referred with their version when there are multiple
+        // packages with the same name in the lockfile
+        for pkg in packages.iter_mut() {
+            if let Some(deps) = pkg.get_mut("dependencies").and_then(|x| x.as_array_mut()) {
+                for dep in deps.iter_mut() {
+                    if let Some(new_dep) = dep.as_str().and_then(|x| changed.get(x)) {
+                        let new_dep = new_dep.clone();
+                        replace_value(dep, new_dep);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+fn replace_value(value: &mut Value, new_value: String) {
+    let decor = value.decor().clone();
+
+    *value = new_value.into();
+    *value.decor_mut() = decor;
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1036,4 +1155,51 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn test_lockfile() {
+        let m = indoc! {r#"
+            [[package]]
+            name = "dep"
+            version = "0.1.0"
+
+            [[package]]
+            name = "dep"
+            version = "0.1.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "top"
+            version = "0.1.0"
+            dependencies = [
+             "dep 0.1.0",
+             "dep 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)",
+            ]
+        "#};
+
+        let mut v = Map::new();
+        v.insert("dep".to_string(), Version::parse("0.2.0").unwrap());
+
+        assert_eq!(
+            change_lockfile(m.into(), &v).unwrap(),
+            indoc! {r#"
+                [[package]]
+                name = "dep"
+                version = "0.2.0"
+
+                [[package]]
+                name = "dep"
+                version = "0.1.0"
+                source = "registry+https://github.com/rust-lang/crates.io-index"
+
+                [[package]]
+                name = "top"
+                version = "0.1.0"
+                dependencies = [
+                 "dep 0.2.0",
+                 "dep 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                ]
+            "#}
+        );
+    }
 }
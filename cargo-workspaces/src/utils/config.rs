@@ -1,4 +1,6 @@
-use crate::utils::{Error, Result};
+use crate::utils::{Error, HooksConfig, LintLevel, Replacement, Result};
+
+use std::collections::BTreeMap;
 
 use serde::Deserialize;
 use serde_json::{from_value, Value};
@@ -21,10 +23,19 @@ where
 #[derive(Deserialize, Default, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
 pub struct PackageConfig {
     pub independent: Option<bool>,
+    pub lints: Option<BTreeMap<String, LintLevel>>,
+    pub features: Option<Vec<String>>,
+    pub all_features: Option<bool>,
+    pub no_default_features: Option<bool>,
+    pub target: Option<String>,
+    pub hooks: Option<HooksConfig>,
+    pub replacements: Option<Vec<Replacement>>,
 }
 
 #[derive(Deserialize, Default, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
 pub struct WorkspaceConfig {
     pub allow_branch: Option<String>,
     pub no_individual_tags: Option<bool>,
+    pub lints: Option<BTreeMap<String, LintLevel>>,
+    pub hooks: Option<HooksConfig>,
 }
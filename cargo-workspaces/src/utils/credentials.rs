@@ -0,0 +1,356 @@
+//! Resolves registry tokens the same way cargo does, see the [cargo reference][1].
+//!
+//! [1]: https://doc.rust-lang.org/cargo/reference/registry-authentication.html
+
+use std::{
+    env,
+    fs::read_to_string,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::utils::{cargo_config_get, debug, warn, Error, Result, INTERNAL_ERR};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::{from_str, json, Value};
+use toml_edit::{Document, Item};
+
+/// Returns the token for the registry with the given name, if there is one.
+/// The credential providers configured for the registry (or globally) are
+/// tried in order, defaulting to `cargo:token`.
+pub fn registry_token(root: &Utf8PathBuf, name: &str, index_url: &str) -> Result<Option<String>> {
+    let providers = match config_list(root, &format!("registries.{}.credential-provider", name)) {
+        Some(provider) => vec![provider],
+        // The last global provider has the highest precedence
+        None => config_list(root, "registry.global-credential-providers")
+            .map(|x| x.into_iter().rev().map(split_provider).collect())
+            .unwrap_or_else(|| vec![vec!["cargo:token".to_string()]]),
+    };
+
+    for provider in providers {
+        debug!("credential provider", provider.join(" "));
+
+        let token = match provider.first().map(|x| x.as_str()) {
+            None => None,
+            Some("cargo:token") => config_token(root, name)?,
+            Some("cargo:token-from-stdout") => token_from_stdout(&provider[1..], name, index_url)?,
+            Some(builtin) if builtin.starts_with("cargo:") => {
+                warn!("unsupported credential provider", builtin);
+                None
+            }
+            Some(_) => credential_plugin(&provider, name, index_url)?,
+        };
+
+        if token.is_some() {
+            return Ok(token);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the token from the environment, the cargo config or the credentials file
+fn config_token(root: &Utf8PathBuf, name: &str) -> Result<Option<String>> {
+    let env_name = format!(
+        "CARGO_REGISTRIES_{}_TOKEN",
+        name.to_uppercase().replace('-', "_")
+    );
+
+    if let Ok(token) = env::var(env_name) {
+        return Ok(Some(token));
+    }
+
+    if let Ok(token) = cargo_config_get(root, &format!("registries.{}.token", name)) {
+        return Ok(Some(token));
+    }
+
+    file_token(tame_index::utils::cargo_home()?.as_std_path(), name)
+}
+
+/// Reads the token from the credentials files in the cargo home, the legacy
+/// `credentials` is used if `credentials.toml` doesn't have the registry
+fn file_token(cargo_home: &Path, name: &str) -> Result<Option<String>> {
+    for file in ["credentials.toml", "credentials"] {
+        if let Ok(credentials) = read_to_string(cargo_home.join(file)) {
+            if let Some(token) = credentials_token(&credentials, name)? {
+                return Ok(Some(token));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn credentials_token(credentials: &str, name: &str) -> Result<Option<String>> {
+    let credentials = credentials.parse::<Document>()?;
+
+    Ok(credentials
+        .get("registries")
+        .and_then(|x| x.get(name))
+        .and_then(|x| x.get("token"))
+        .and_then(Item::as_str)
+        .map(|x| x.to_string()))
+}
+
+/// Runs the command and uses the first line of its output as the token
+fn token_from_stdout(command: &[String], name: &str, index_url: &str) -> Result<Option<String>> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| credential_error(name, "missing command for cargo:token-from-stdout"))?;
+
+    let output = Command::new(program)
+        .args(args)
+        .env("CARGO_REGISTRY_INDEX_URL", index_url)
+        .env("CARGO_REGISTRY_NAME_OPT", name)
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(credential_error(name, &format!("{} failed", program)));
+    }
+
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .next()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty()))
+}
+
+/// Asks a credential provider that speaks the cargo credential protocol
+fn credential_plugin(command: &[String], name: &str, index_url: &str) -> Result<Option<String>> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .arg("--cargo-plugin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect(INTERNAL_ERR);
+    let mut stdout = BufReader::new(child.stdout.take().expect(INTERNAL_ERR));
+
+    // The provider says hello with the protocol versions it supports
+    let mut hello = String::new();
+    stdout.read_line(&mut hello)?;
+
+    let request = json!({
+        "v": 1,
+        "registry": { "index-url": index_url, "name": name },
+        "kind": "get",
+        "operation": "read",
+        "args": [],
+    });
+
+    writeln!(stdin, "{}", request)?;
+
+    let mut response = String::new();
+    stdout.read_line(&mut response)?;
+
+    drop(stdin);
+    child.wait()?;
+
+    let response = from_str::<Value>(&response)?;
+
+    if let Some(token) = response.pointer("/Ok/token").and_then(Value::as_str) {
+        return Ok(Some(token.to_string()));
+    }
+
+    match response.pointer("/Err/kind").and_then(Value::as_str) {
+        Some("not-found") | Some("url-not-supported") => Ok(None),
+        _ => Err(credential_error(
+            name,
+            response
+                .pointer("/Err/message")
+                .and_then(Value::as_str)
+                .unwrap_or("unexpected response from credential provider"),
+        )),
+    }
+}
+
+fn credential_error(name: &str, message: &str) -> Error {
+    Error::Credential(name.to_string(), message.to_string())
+}
+
+/// Reads a config value which can be either a string of space separated
+/// values or an array
+fn config_list(root: &Utf8Path, name: &str) -> Option<Vec<String>> {
+    let value = cargo_config_get(root, name).ok()?;
+
+    // `cargo_config_get` strips the quotes around strings
+    if !value.starts_with('[') {
+        return Some(split_provider(value));
+    }
+
+    let value = format!("value = {}", value).parse::<Document>().ok()?;
+
+    Some(
+        value["value"]
+            .as_array()?
+            .iter()
+            .filter_map(|x| x.as_str().map(|x| x.to_string()))
+            .collect(),
+    )
+}
+
+fn split_provider(provider: String) -> Vec<String> {
+    provider.split_whitespace().map(|x| x.to_string()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+
+    fn config_root(config: &str) -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+        create_dir_all(root.join(".cargo")).unwrap();
+        write(root.join(".cargo").join("config.toml"), config).unwrap();
+
+        (dir, root)
+    }
+
+    #[test]
+    fn test_file_token_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(
+            dir.path().join("credentials.toml"),
+            "[registries.other]\ntoken = \"other-token\"\n",
+        )
+        .unwrap();
+        write(
+            dir.path().join("credentials"),
+            "[registries.internal]\ntoken = \"internal-token\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file_token(dir.path(), "other").unwrap(),
+            Some("other-token".to_string())
+        );
+        assert_eq!(
+            file_token(dir.path(), "internal").unwrap(),
+            Some("internal-token".to_string())
+        );
+        assert_eq!(file_token(dir.path(), "mirror").unwrap(), None);
+    }
+
+    #[test]
+    fn test_registry_provider() {
+        let (_dir, root) = config_root(
+            r#"
+            [registry]
+            global-credential-providers = ["cargo:token-from-stdout echo global-token"]
+
+            [registries.ws-test-stdout]
+            credential-provider = "cargo:token-from-stdout echo stdout-token"
+            "#,
+        );
+
+        assert_eq!(
+            registry_token(&root, "ws-test-stdout", "https://example.com").unwrap(),
+            Some("stdout-token".to_string())
+        );
+        assert_eq!(
+            registry_token(&root, "ws-test-other", "https://example.com").unwrap(),
+            Some("global-token".to_string())
+        );
+    }
+
+    #[test]
+    fn test_global_providers_precedence() {
+        let (_dir, root) = config_root(
+            r#"
+            [registry]
+            global-credential-providers = [
+                "cargo:token-from-stdout echo first-token",
+                "cargo:token-from-stdout echo last-token",
+            ]
+            "#,
+        );
+
+        assert_eq!(
+            registry_token(&root, "ws-test-global", "https://example.com").unwrap(),
+            Some("last-token".to_string())
+        );
+    }
+
+    #[test]
+    fn test_token_from_stdout() {
+        let command = [
+            "sh",
+            "-c",
+            "echo \"$CARGO_REGISTRY_NAME_OPT-token\"; echo ignored",
+        ]
+        .map(|x| x.to_string());
+
+        assert_eq!(
+            token_from_stdout(&command, "internal", "https://example.com").unwrap(),
+            Some("internal-token".to_string())
+        );
+
+        let command = ["false".to_string()];
+
+        assert!(matches!(
+            token_from_stdout(&command, "internal", "https://example.com"),
+            Err(Error::Credential(name, _)) if name == "internal"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credential_plugin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let plugin = dir.path().join("plugin");
+
+        // Answers with a token for the `internal` registry only
+        write(
+            &plugin,
+            r#"#!/bin/sh
+echo '{"v":[1]}'
+read request
+case "$request" in
+    *'"name":"internal"'*)
+        echo '{"Ok":{"kind":"get","token":"plugin-token","cache":"session","operation_independent":true}}' ;;
+    *)
+        echo '{"Err":{"kind":"not-found"}}' ;;
+esac
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let command = [plugin.to_str().unwrap().to_string()];
+
+        assert_eq!(
+            credential_plugin(&command, "internal", "https://example.com").unwrap(),
+            Some("plugin-token".to_string())
+        );
+        assert_eq!(
+            credential_plugin(&command, "mirror", "https://example.com").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_credentials_token() {
+        let credentials = r#"
+            [registry]
+            token = "crates-io-token"
+
+            [registries.internal]
+            token = "internal-token"
+        "#;
+
+        assert_eq!(
+            credentials_token(credentials, "internal").unwrap(),
+            Some("internal-token".to_string())
+        );
+        assert_eq!(credentials_token(credentials, "mirror").unwrap(), None);
+    }
+}
@@ -1,52 +1,327 @@
 use std::{
-    fs::{read_to_string, write},
-    path::Path,
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write},
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
-use cargo_metadata::{Dependency, DependencyKind, Package};
+use cargo_metadata::{Dependency, DependencyKind, Metadata, Package};
+use lazy_static::lazy_static;
 use semver::VersionReq;
-use toml_edit::Document;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml_edit::{value, Document, TableLike};
 
-use crate::utils::Result;
+use crate::utils::{warn, Result};
 
-/// Removes all dev-dependencies from a Cargo.toml then restores the file when dropped.
+lazy_static! {
+    /// Journals of the manifests currently modified by this process
+    static ref ACTIVE_JOURNALS: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+}
+
+/// Backup of a manifest written before removing its dev-dependencies, so that
+/// it can be restored even if the process is killed
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    manifest_path: PathBuf,
+    original_toml: String,
+    /// Hash of the manifest written without the dev-dependencies, it's only
+    /// restored if it wasn't changed since
+    stripped_hash: String,
+}
+
+/// How to change the manifest of a crate whose dev-dependencies refer to
+/// workspace crates with versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevDepsMode {
+    /// Leave the manifest as is
+    Keep,
+    /// Remove all the dev-dependencies
+    RemoveAll,
+    /// Only make the dev-dependencies on workspace crates path-only, or remove
+    /// them if they have no path
+    StripWorkspace,
+}
+
+impl DevDepsMode {
+    pub fn new(no_remove_dev_deps: bool, only_workspace_dev_deps: bool) -> Self {
+        if no_remove_dev_deps {
+            Self::Keep
+        } else if only_workspace_dev_deps {
+            Self::StripWorkspace
+        } else {
+            Self::RemoveAll
+        }
+    }
+}
+
+/// Removes dev-dependencies from a Cargo.toml then restores the file when dropped.
 pub struct DevDependencyRemover {
-    manifest_path: std::path::PathBuf,
+    manifest_path: PathBuf,
     original_toml: String,
+    journal_path: PathBuf,
 }
 
 impl DevDependencyRemover {
-    pub fn remove_dev_deps(manifest_path: &Path) -> Result<Self> {
-        let original_toml = read_to_string(manifest_path)?;
-        let mut document = original_toml.parse::<Document>()?;
+    /// Removes the dev-dependencies after writing a journal to `journal_dir`
+    pub fn remove_dev_deps(manifest_path: &Path, journal_dir: &Path) -> Result<Self> {
+        Self::modify(manifest_path, journal_dir, |document| {
+            document.as_table_mut().remove("dev-dependencies");
+
+            if let Some(table) = document.as_table_mut().get_mut("target") {
+                if let Some(table) = table.as_table_mut() {
+                    table.iter_mut().for_each(|(_, value)| {
+                        if let Some(table) = value.as_table_mut() {
+                            table.remove("dev-dependencies");
+                        }
+                    });
+                }
+            }
+        })
+    }
+
+    /// Only changes the dev-dependencies on the given workspace crates after
+    /// writing a journal to `journal_dir`. Those with a path lose their version
+    /// so that cargo leaves them out of the published crate, the others are removed.
+    /// Those inherited from the workspace at `workspace_root` are only changed
+    /// if the workspace dependency has a path.
+    pub fn strip_workspace_dev_deps(
+        manifest_path: &Path,
+        journal_dir: &Path,
+        workspace_root: &Path,
+        pkgs: &[(Package, String)],
+    ) -> Result<Self> {
+        let workspace = read_to_string(workspace_root.join("Cargo.toml"))?.parse::<Document>()?;
+        let workspace_deps = workspace
+            .get("workspace")
+            .and_then(|x| x.get("dependencies"))
+            .and_then(|x| x.as_table_like());
+
+        Self::modify(manifest_path, journal_dir, |document| {
+            for table in dev_deps_tables(document) {
+                let mut inherited = vec![];
+                let mut keys = vec![];
+
+                for (key, item) in table.iter() {
+                    let workspace_dep =
+                        if item.get("workspace").and_then(|x| x.as_bool()) == Some(true) {
+                            match workspace_deps.and_then(|x| x.get(key)) {
+                                Some(dep) => Some(dep),
+                                None => continue,
+                            }
+                        } else {
+                            None
+                        };
+
+                    let dep = workspace_dep.unwrap_or(item);
+                    let name = dep.get("package").and_then(|x| x.as_str()).unwrap_or(key);
+
+                    if !pkgs.iter().any(|(pkg, _)| pkg.name == name) {
+                        continue;
+                    }
+
+                    match workspace_dep {
+                        Some(dep) => {
+                            if let Some(path) = dep.get("path").and_then(|x| x.as_str()) {
+                                let path = workspace_root.join(path).to_string_lossy().to_string();
+                                inherited.push((key.to_string(), path, name.to_string()));
+                            }
+                        }
+                        None => keys.push(key.to_string()),
+                    }
+                }
 
-        document.as_table_mut().remove("dev-dependencies");
+                // The path of the workspace dependency replaces the inherited version
+                for (key, path, name) in inherited {
+                    if let Some(dep) = table.get_mut(&key).and_then(|x| x.as_table_like_mut()) {
+                        dep.remove("workspace");
+                        dep.insert("path", value(path));
 
-        if let Some(table) = document.as_table_mut().get_mut("target") {
-            if let Some(table) = table.as_table_mut() {
-                table.iter_mut().for_each(|(_, value)| {
-                    if let Some(table) = value.as_table_mut() {
-                        table.remove("dev-dependencies");
+                        if name != key {
+                            dep.insert("package", value(name));
+                        }
+                    }
+                }
+
+                for key in keys {
+                    match table.get_mut(&key).and_then(|x| x.as_table_like_mut()) {
+                        Some(dep) if dep.contains_key("path") => {
+                            dep.remove("version");
+                            dep.remove("registry");
+                        }
+                        _ => {
+                            table.remove(&key);
+                        }
                     }
-                });
+                }
             }
+        })
+    }
+
+    /// Applies the mode, which must not be [`DevDepsMode::Keep`]
+    pub fn new(
+        manifest_path: &Path,
+        journal_dir: &Path,
+        workspace_root: &Path,
+        mode: DevDepsMode,
+        pkgs: &[(Package, String)],
+    ) -> Result<Self> {
+        match mode {
+            DevDepsMode::StripWorkspace => {
+                Self::strip_workspace_dev_deps(manifest_path, journal_dir, workspace_root, pkgs)
+            }
+            _ => Self::remove_dev_deps(manifest_path, journal_dir),
         }
+    }
 
-        write(manifest_path, document.to_string())?;
+    fn modify(
+        manifest_path: &Path,
+        journal_dir: &Path,
+        change: impl FnOnce(&mut Document),
+    ) -> Result<Self> {
+        let original_toml = read_to_string(manifest_path)?;
+        let mut document = original_toml.parse::<Document>()?;
 
-        Ok(Self {
+        change(&mut document);
+
+        let stripped_toml = document.to_string();
+
+        let journal_path = journal_dir.join(format!(
+            "{:x}.json",
+            Sha256::digest(manifest_path.to_string_lossy().as_bytes())
+        ));
+
+        let journal = Journal {
             manifest_path: manifest_path.to_owned(),
             original_toml,
+            stripped_hash: hash(&stripped_toml),
+        };
+
+        // Write the journal atomically so that a partial one is never restored
+        create_dir_all(journal_dir)?;
+        let temp_path = journal_path.with_extension("tmp");
+        write(&temp_path, serde_json::to_string(&journal)?)?;
+        rename(&temp_path, &journal_path)?;
+
+        active_journals().push(journal_path.clone());
+
+        write(manifest_path, stripped_toml)?;
+
+        Ok(Self {
+            manifest_path: journal.manifest_path,
+            original_toml: journal.original_toml,
+            journal_path,
         })
     }
 }
 
+/// Returns the `dev-dependencies` tables, including the target specific ones
+fn dev_deps_tables(document: &mut Document) -> Vec<&mut dyn TableLike> {
+    let mut tables = vec![];
+
+    for (key, item) in document.as_table_mut().iter_mut() {
+        match key.get() {
+            "dev-dependencies" => tables.extend(item.as_table_like_mut()),
+            "target" => {
+                if let Some(targets) = item.as_table_like_mut() {
+                    for (_, target) in targets.iter_mut() {
+                        tables.extend(
+                            target
+                                .get_mut("dev-dependencies")
+                                .and_then(|x| x.as_table_like_mut()),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tables
+}
+
 impl Drop for DevDependencyRemover {
     fn drop(&mut self) {
-        let _ = write(&self.manifest_path, &self.original_toml);
+        if write(&self.manifest_path, &self.original_toml).is_ok() {
+            let _ = remove_file(&self.journal_path);
+        }
+
+        active_journals().retain(|x| x != &self.journal_path);
+    }
+}
+
+fn active_journals() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    ACTIVE_JOURNALS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn hash(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+/// Directory of the journals for the manifests of the workspace. It's in the
+/// cargo home so that `cargo clean` doesn't remove it.
+pub fn journal_dir(metadata: &Metadata) -> Result<PathBuf> {
+    Ok(tame_index::utils::cargo_home()?
+        .join("ws-dev-deps")
+        .join(hash(metadata.workspace_root.as_str()))
+        .into())
+}
+
+/// Restores the manifests modified by this process, used when interrupted
+pub fn restore_active_dev_deps() {
+    for journal in active_journals().drain(..) {
+        let _ = restore_journal(&journal);
     }
 }
 
+/// Restores the manifests left with their dev-dependencies removed by an
+/// earlier run that didn't finish. Returns the restored manifests, those
+/// changed since are left alone.
+pub fn restore_dev_deps(journal_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut restored = vec![];
+
+    if !journal_dir.exists() {
+        return Ok(restored);
+    }
+
+    for entry in read_dir(journal_dir)? {
+        let path = entry?.path();
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => restored.extend(restore_journal(&path)?),
+            // Journal that was never completely written
+            Some("tmp") => remove_file(&path)?,
+            _ => {}
+        }
+    }
+
+    restored.sort();
+    Ok(restored)
+}
+
+/// Restores the manifest of the journal if it still is the one written without
+/// the dev-dependencies. Returns the manifest if it was restored.
+fn restore_journal(journal_path: &Path) -> Result<Option<PathBuf>> {
+    let journal = serde_json::from_str::<Journal>(&read_to_string(journal_path)?)?;
+
+    let unchanged =
+        read_to_string(&journal.manifest_path).is_ok_and(|x| hash(&x) == journal.stripped_hash);
+
+    if unchanged {
+        write(&journal.manifest_path, &journal.original_toml)?;
+    } else {
+        warn!(
+            "manifest changed since its dev-dependencies were removed, not restoring",
+            journal.manifest_path.display()
+        );
+    }
+
+    remove_file(journal_path)?;
+
+    Ok(Some(journal.manifest_path).filter(|_| unchanged))
+}
+
 pub fn should_remove_dev_deps(deps: &[Dependency], pkgs: &[(Package, String)]) -> bool {
     let mut names = vec![];
     let no_version = VersionReq::parse("*").unwrap();
@@ -100,7 +375,9 @@ mod tests {
 
         write(&manifest_path, original_toml).unwrap();
 
-        let remover = DevDependencyRemover::remove_dev_deps(&manifest_path).unwrap();
+        let remover =
+            DevDependencyRemover::remove_dev_deps(&manifest_path, &tempdir.path().join("journal"))
+                .unwrap();
 
         assert_eq!(
             read_to_string(&manifest_path).unwrap(),
@@ -121,6 +398,72 @@ mod tests {
         assert_eq!(read_to_string(&manifest_path).unwrap(), original_toml);
     }
 
+    #[test]
+    fn test_restore_dev_deps() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest_path = tempdir.path().join("Cargo.toml");
+        let journal_dir = tempdir.path().join("journal");
+
+        let original_toml = r#"
+        [package]
+        name = "foo"
+        version = "0.1.0"
+
+        [dev-dependencies]
+        dep2 = "2.0.1"
+        "#;
+
+        write(&manifest_path, original_toml).unwrap();
+
+        // Simulate the process being killed before the manifest is restored
+        std::mem::forget(
+            DevDependencyRemover::remove_dev_deps(&manifest_path, &journal_dir).unwrap(),
+        );
+
+        assert_ne!(read_to_string(&manifest_path).unwrap(), original_toml);
+        assert_eq!(
+            restore_dev_deps(&journal_dir).unwrap(),
+            vec![manifest_path.clone()]
+        );
+        assert_eq!(read_to_string(&manifest_path).unwrap(), original_toml);
+        assert!(restore_dev_deps(&journal_dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore_dev_deps_changed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest_path = tempdir.path().join("Cargo.toml");
+        let journal_dir = tempdir.path().join("journal");
+
+        let original_toml = r#"
+        [package]
+        name = "foo"
+        version = "0.1.0"
+
+        [dev-dependencies]
+        dep2 = "2.0.1"
+        "#;
+
+        write(&manifest_path, original_toml).unwrap();
+
+        std::mem::forget(
+            DevDependencyRemover::remove_dev_deps(&manifest_path, &journal_dir).unwrap(),
+        );
+
+        // The manifest was edited after the process was killed
+        let edited_toml = r#"
+        [package]
+        name = "foo"
+        version = "0.2.0"
+        "#;
+
+        write(&manifest_path, edited_toml).unwrap();
+
+        assert!(restore_dev_deps(&journal_dir).unwrap().is_empty());
+        assert_eq!(read_to_string(&manifest_path).unwrap(), edited_toml);
+        assert_eq!(read_dir(&journal_dir).unwrap().count(), 0);
+    }
+
     #[test]
     fn test_remove_dev_deps_target() {
         let tempdir = tempfile::tempdir().unwrap();
@@ -142,7 +485,9 @@ mod tests {
 
         write(&manifest_path, original_toml).unwrap();
 
-        let remover = DevDependencyRemover::remove_dev_deps(&manifest_path).unwrap();
+        let remover =
+            DevDependencyRemover::remove_dev_deps(&manifest_path, &tempdir.path().join("journal"))
+                .unwrap();
 
         assert_eq!(
             read_to_string(&manifest_path).unwrap(),
@@ -163,6 +508,170 @@ mod tests {
         assert_eq!(read_to_string(&manifest_path).unwrap(), original_toml);
     }
 
+    #[test]
+    fn test_strip_workspace_dev_deps() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest_path = tempdir.path().join("Cargo.toml");
+
+        let original_toml = r#"
+        [package]
+        name = "foo" # A comment
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+
+        [dev-dependencies]
+        bar = { version = "0.1.0", path = "bar" }
+        baz = "1.0.0"
+
+        [target.'cfg(unix)'.dev-dependencies]
+        renamed = { package = "bar", version = "0.1.0" }
+
+        [workspace]
+        members = [".", "bar"]
+        "#;
+
+        write(&manifest_path, original_toml).unwrap();
+
+        let member_toml = r#"
+        [package]
+        name = "bar" # A comment
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+        "#;
+
+        create_dir(tempdir.path().join("bar")).unwrap();
+        write(tempdir.path().join("bar").join("Cargo.toml"), member_toml).unwrap();
+
+        let (_, pkgs) = args(&manifest_path, "foo");
+
+        let remover = DevDependencyRemover::strip_workspace_dev_deps(
+            &manifest_path,
+            &tempdir.path().join("journal"),
+            tempdir.path(),
+            &pkgs,
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_to_string(&manifest_path).unwrap(),
+            r#"
+        [package]
+        name = "foo" # A comment
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+
+        [dev-dependencies]
+        bar = { path = "bar" }
+        baz = "1.0.0"
+
+        [target.'cfg(unix)'.dev-dependencies]
+
+        [workspace]
+        members = [".", "bar"]
+        "#
+        );
+
+        drop(remover);
+
+        assert_eq!(read_to_string(&manifest_path).unwrap(), original_toml);
+    }
+
+    #[test]
+    fn test_strip_workspace_dev_deps_inherited() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let manifest_path = tempdir.path().join("Cargo.toml");
+
+        let original_toml = r#"
+        [package]
+        name = "foo"
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+
+        [dev-dependencies]
+        bar = { workspace = true }
+        external = { workspace = true }
+        renamed = { workspace = true, features = ["baz"] }
+
+        [workspace]
+        members = [".", "bar"]
+
+        [workspace.dependencies]
+        bar = { version = "0.1.0", path = "bar" }
+        external = { package = "bar", version = "0.1.0" }
+        renamed = { package = "bar", version = "0.1.0", path = "bar" }
+        "#;
+
+        write(&manifest_path, original_toml).unwrap();
+
+        let member_toml = r#"
+        [package]
+        name = "bar"
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+
+        [features]
+        baz = []
+        "#;
+
+        create_dir(tempdir.path().join("bar")).unwrap();
+        write(tempdir.path().join("bar").join("Cargo.toml"), member_toml).unwrap();
+
+        let (_, pkgs) = args(&manifest_path, "foo");
+
+        let remover = DevDependencyRemover::strip_workspace_dev_deps(
+            &manifest_path,
+            &tempdir.path().join("journal"),
+            tempdir.path(),
+            &pkgs,
+        )
+        .unwrap();
+
+        let bar_path = tempdir.path().join("bar");
+        let bar_path = bar_path.to_str().unwrap();
+
+        assert_eq!(
+            read_to_string(&manifest_path).unwrap(),
+            format!(
+                r#"
+        [package]
+        name = "foo"
+        version = "0.1.0"
+
+        [lib]
+        path = "lib.rs"
+
+        [dev-dependencies]
+        bar = {{ path = "{0}" }}
+        external = {{ workspace = true }}
+        renamed = {{ features = ["baz"] , path = "{0}", package = "bar" }}
+
+        [workspace]
+        members = [".", "bar"]
+
+        [workspace.dependencies]
+        bar = {{ version = "0.1.0", path = "bar" }}
+        external = {{ package = "bar", version = "0.1.0" }}
+        renamed = {{ package = "bar", version = "0.1.0", path = "bar" }}
+        "#,
+                bar_path
+            )
+        );
+
+        drop(remover);
+
+        assert_eq!(read_to_string(&manifest_path).unwrap(), original_toml);
+    }
+
     #[test]
     fn test_should_remove_dev_deps_normal() {
         let tempdir = tempfile::tempdir().unwrap();
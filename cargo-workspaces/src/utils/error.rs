@@ -1,3 +1,5 @@
+use crate::utils::PublishFailure;
+
 use lazy_static::lazy_static;
 use oclif::{term::ERR_YELLOW, CliError};
 use thiserror::Error;
@@ -80,8 +82,36 @@ pub enum Error {
 
     #[error("unable to verify package {0}")]
     Verify(String),
-    #[error("unable to publish package {0}")]
-    Publish(String),
+    #[error("unable to verify package {0} against the packaged sources of its dependencies")]
+    UnusedPatch(String),
+    #[error("unable to publish package {0}: {1}")]
+    Publish(String, PublishFailure),
+    #[error("{0} hook failed for {1}")]
+    Hook(String, String),
+    #[error("unable to replace {1} in {0}")]
+    Replacement(String, String),
+    #[error("unable to find file {0} for the replacement")]
+    ReplacementNotFound(String),
+    #[error("no public crate matches {0}")]
+    NoMatchingPackage(String),
+    #[error("no independent crate matches {0}")]
+    NoMatchingIndependent(String),
+    #[error("no crate has feature {0}")]
+    NoMatchingFeature(String),
+    #[error(
+        "--package needs --publish-as-is or --from-tag since versioning would change every crate"
+    )]
+    PackageWithVersioning,
+    #[error("unable to yank package {0}")]
+    Yank(String),
+    #[error("unable to manage owners of package {0}")]
+    Owner(String),
+    #[error("found {0} problem(s) that would make publishing fail")]
+    NotReady(usize),
+    #[error("{0} lint(s) failed")]
+    Lint(usize),
+    #[error("unknown lint {0}")]
+    UnknownLint(String),
     #[error("unable to update Cargo.lock")]
     Update,
 
@@ -128,10 +158,26 @@ pub enum Error {
     NotAdded(String, String),
     #[error("unable to commit to git, out = {0}, err = {1}")]
     NotCommitted(String, String),
+    #[error("tag {0} not found or not a release tag")]
+    TagNotFound(String),
+    #[error("tags already exist: {0}")]
+    TagsExist(String),
+    #[error("unable to list the tags of remote {0}: {1}")]
+    RemoteTags(String, String),
+    #[error("unable to count the commits in {0}: {1}")]
+    NotCounted(String, String),
     #[error("unable to tag {0}, out = {1}, err = {2}")]
     NotTagged(String, String, String),
+    #[error("unable to check out {0}, out = {1}, err = {2}")]
+    NotCheckedOut(String, String, String),
+    #[error("unable to read the workspace at {0}: {1}")]
+    WorktreeMetadata(String, String),
     #[error("unable to push to remote, out = {0}, err = {1}")]
     NotPushed(String, String),
+    #[error("{0}, and unable to restore the previous state: {1}")]
+    NotRestored(String, String),
+    #[error("unable to record the uncommitted changes: {0}")]
+    NotStashed(String),
 
     #[error("no changes detected")]
     NoChanges,
@@ -140,8 +186,12 @@ pub enum Error {
     BadConfigGetOutput(String),
     #[error("crates index error: {0}")]
     CratesRegistry(#[from] tame_index::Error),
+    #[error("the replacements of source {0} form a cycle")]
+    SourceCycle(String),
     #[error("unsupported crates index type")]
     UnsupportedCratesIndexType,
+    #[error("unable to get token for registry {0}: {1}")]
+    Credential(String, String),
     #[error("crates index error: {0}")]
     CratesReqwest(#[from] tame_index::external::reqwest::Error),
 
@@ -157,6 +207,8 @@ pub enum Error {
     #[error("{0}")]
     Globset(#[from] globset::Error),
     #[error("{0}")]
+    Regex(#[from] regex::Error),
+    #[error("{0}")]
     Serde(#[from] serde_json::Error),
     #[error("{0}")]
     Io(#[from] io::Error),
@@ -177,7 +229,29 @@ impl CliError for Error {
                 id: format!("{}", ERR_YELLOW.apply_to(id)),
             },
             Self::Verify(pkg) => Self::Verify(format!("{}", ERR_YELLOW.apply_to(pkg))),
-            Self::Publish(pkg) => Self::Publish(format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::SourceCycle(name) => Self::SourceCycle(format!("{}", ERR_YELLOW.apply_to(name))),
+            Self::UnusedPatch(pkg) => Self::UnusedPatch(format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::NoMatchingPackage(pattern) => {
+                Self::NoMatchingPackage(format!("{}", ERR_YELLOW.apply_to(pattern)))
+            }
+            Self::NoMatchingIndependent(pattern) => {
+                Self::NoMatchingIndependent(format!("{}", ERR_YELLOW.apply_to(pattern)))
+            }
+            Self::NoMatchingFeature(feature) => {
+                Self::NoMatchingFeature(format!("{}", ERR_YELLOW.apply_to(feature)))
+            }
+            Self::Yank(pkg) => Self::Yank(format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::Owner(pkg) => Self::Owner(format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::Replacement(file, search) => {
+                Self::Replacement(format!("{}", ERR_YELLOW.apply_to(file)), search)
+            }
+            Self::ReplacementNotFound(file) => {
+                Self::ReplacementNotFound(format!("{}", ERR_YELLOW.apply_to(file)))
+            }
+            Self::Hook(hook, pkg) => Self::Hook(hook, format!("{}", ERR_YELLOW.apply_to(pkg))),
+            Self::Publish(pkg, failure) => {
+                Self::Publish(format!("{}", ERR_YELLOW.apply_to(pkg)), failure)
+            }
             Self::MustContainPercentN(val) => {
                 Self::MustContainPercentN(format!("{}", ERR_YELLOW.apply_to(val)))
             }
@@ -196,6 +270,18 @@ impl CliError for Error {
                 branch: format!("{}", ERR_YELLOW.apply_to(branch)),
                 pattern: format!("{}", ERR_YELLOW.apply_to(pattern)),
             },
+            Self::UnknownLint(name) => Self::UnknownLint(format!("{}", ERR_YELLOW.apply_to(name))),
+            Self::Credential(name, message) => {
+                Self::Credential(format!("{}", ERR_YELLOW.apply_to(name)), message)
+            }
+            Self::TagNotFound(tag) => Self::TagNotFound(format!("{}", ERR_YELLOW.apply_to(tag))),
+            Self::TagsExist(tags) => Self::TagsExist(format!("{}", ERR_YELLOW.apply_to(tags))),
+            Self::RemoteTags(remote, err) => {
+                Self::RemoteTags(format!("{}", ERR_YELLOW.apply_to(remote)), err)
+            }
+            Self::NotCheckedOut(rev, out, err) => {
+                Self::NotCheckedOut(format!("{}", ERR_YELLOW.apply_to(rev)), out, err)
+            }
             Self::NotTagged(tag, out, err) => {
                 Self::NotTagged(format!("{}", ERR_YELLOW.apply_to(tag)), out, err)
             }
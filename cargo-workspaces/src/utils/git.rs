@@ -1,6 +1,4 @@
-use crate::utils::{
-    debug, info, validate_value_containing_name, Error, WorkspaceConfig, INTERNAL_ERR,
-};
+use crate::utils::{debug, info, Error, TagOpt, WorkspaceConfig, INTERNAL_ERR};
 
 use camino::Utf8PathBuf;
 use clap::Parser;
@@ -31,6 +29,44 @@ pub fn git(root: &Utf8PathBuf, args: &[&str]) -> Result<(ExitStatus, String, Str
     ))
 }
 
+/// A temporary git worktree that is removed when dropped
+pub struct Worktree {
+    root: Utf8PathBuf,
+    pub path: Utf8PathBuf,
+}
+
+impl Worktree {
+    /// Checks out the revision into a detached worktree at the given path,
+    /// replacing any worktree left there by an earlier run
+    pub fn add(root: &Utf8PathBuf, path: &Utf8PathBuf, rev: &str) -> Result<Self, Error> {
+        if path.exists() {
+            git(root, &["worktree", "remove", "--force", path.as_str()])?;
+        }
+
+        git(root, &["worktree", "prune"])?;
+
+        let added = git(root, &["worktree", "add", "--detach", path.as_str(), rev])?;
+
+        if !added.0.success() {
+            return Err(Error::NotCheckedOut(rev.to_string(), added.1, added.2));
+        }
+
+        Ok(Self {
+            root: root.clone(),
+            path: path.clone(),
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let _ = git(
+            &self.root,
+            &["worktree", "remove", "--force", self.path.as_str()],
+        );
+    }
+}
+
 #[derive(Debug, Parser)]
 #[clap(next_help_heading = "GIT OPTIONS")]
 pub struct GitOpt {
@@ -46,6 +82,10 @@ pub struct GitOpt {
     #[clap(long, value_name = "PATTERN", forbid_empty_values(true))]
     pub allow_branch: Option<String>,
 
+    /// Set from the `version` command, since publishing needs the tags
+    #[clap(skip)]
+    pub release_branch: Option<String>,
+
     /// Amend the existing commit, instead of generating a new one
     #[clap(long)]
     pub amend: bool,
@@ -71,19 +111,8 @@ pub struct GitOpt {
     #[clap(long)]
     pub no_global_tag: bool,
 
-    /// Customize tag prefix (can be empty)
-    #[clap(long, default_value = "v", value_name = "PREFIX")]
-    pub tag_prefix: String,
-
-    /// Customize prefix for individual tags (should contain `%n`)
-    #[clap(
-        long,
-        default_value = "%n@",
-        value_name = "PREFIX",
-        validator = validate_value_containing_name,
-        forbid_empty_values(true)
-    )]
-    pub individual_tag_prefix: String,
+    #[clap(flatten)]
+    pub tag: TagOpt,
 
     /// Do not push generated commit and tags to git remote
     #[clap(long, conflicts_with_all = &["git-remote"])]
@@ -201,6 +230,7 @@ impl GitOpt {
         new_versions: &Map<String, Version>,
         branch: Option<String>,
         config: &WorkspaceConfig,
+        created_tags: &mut Vec<String>,
     ) -> Result<(), Error> {
         if !self.no_git_commit {
             info!("version", "committing changes");
@@ -214,28 +244,12 @@ impl GitOpt {
 
             let mut args = vec!["commit".to_string()];
 
-            if self.amend {
-                args.push("--amend".to_string());
-                args.push("--no-edit".to_string());
-            } else {
+            if let Some(msg) = self.commit_message(new_version, new_versions) {
                 args.push("-m".to_string());
-
-                let mut msg = "Release %v";
-
-                if let Some(supplied) = &self.message {
-                    msg = supplied;
-                }
-
-                let mut msg = self.commit_msg(msg, new_versions);
-
-                msg = msg.replace(
-                    "%v",
-                    &new_version
-                        .as_ref()
-                        .map_or("independent packages".to_string(), |x| format!("{}", x)),
-                );
-
                 args.push(msg);
+            } else {
+                args.push("--amend".to_string());
+                args.push("--no-edit".to_string());
             }
 
             let committed = git(root, &args.iter().map(|x| x.as_str()).collect::<Vec<_>>())?;
@@ -244,28 +258,35 @@ impl GitOpt {
                 return Err(Error::NotCommitted(committed.1, committed.2));
             }
 
-            if !self.no_git_tag {
-                info!("version", "tagging");
+            let tags = self.tags(new_version, new_versions, config);
 
-                if !self.no_global_tag {
-                    if let Some(version) = new_version {
-                        let tag = format!("{}{}", &self.tag_prefix, version);
-                        self.tag(root, &tag, &tag)?;
-                    }
-                }
+            if !tags.is_empty() {
+                info!("version", "tagging");
 
-                if !(self.no_individual_tags || config.no_individual_tags.unwrap_or_default()) {
-                    for (p, v) in new_versions {
-                        let tag = format!("{}{}", self.individual_tag_prefix.replace("%n", p), v);
-                        self.tag(root, &tag, &tag)?;
-                    }
+                for tag in tags {
+                    create_tag(root, &tag, &tag)?;
+                    created_tags.push(tag);
                 }
             }
 
             if !self.no_git_push {
                 info!("git", "pushing");
 
-                let pushed = git(root, &["push", "--follow-tags", &self.git_remote, &branch])?;
+                let pushed = if self.release_branch.is_some() {
+                    git(root, &["push", "--set-upstream", &self.git_remote, &branch])?
+                } else {
+                    // Either the commit and all the tags make it to the remote or nothing does
+                    git(
+                        root,
+                        &[
+                            "push",
+                            "--atomic",
+                            "--follow-tags",
+                            &self.git_remote,
+                            &branch,
+                        ],
+                    )?
+                };
 
                 if !pushed.0.success() {
                     return Err(Error::NotPushed(pushed.1, pushed.2));
@@ -276,11 +297,101 @@ impl GitOpt {
         Ok(())
     }
 
-    fn tag(&self, root: &Utf8PathBuf, tag: &str, msg: &str) -> Result<(), Error> {
-        let tagged = git(root, &["tag", tag, "-m", msg])?;
+    /// Returns the message of the version commit or `None` when amending
+    pub fn commit_message(
+        &self,
+        new_version: &Option<Version>,
+        new_versions: &Map<String, Version>,
+    ) -> Option<String> {
+        if self.amend {
+            return None;
+        }
+
+        let msg = self.message.as_deref().unwrap_or("Release %v");
+
+        Some(
+            self.commit_msg(msg, new_versions).replace(
+                "%v",
+                &new_version
+                    .as_ref()
+                    .map_or("independent packages".to_string(), |x| format!("{}", x)),
+            ),
+        )
+    }
+
+    /// Returns the name of the release branch for the new versions, if one was asked for
+    pub fn release_branch(
+        &self,
+        root: &Utf8PathBuf,
+        new_version: &Option<Version>,
+    ) -> Result<Option<String>, Error> {
+        let name = match &self.release_branch {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        let version = match new_version {
+            Some(version) => version.to_string(),
+            // Independent versions have nothing in common, so use the commit they are based on
+            None => format!(
+                "independent-{}",
+                git(root, &["rev-parse", "--short", "HEAD"])?.1
+            ),
+        };
+
+        Ok(Some(name.replace("%v", &version)))
+    }
+
+    /// Returns the tags that would be created for the version commit
+    pub fn tags(
+        &self,
+        new_version: &Option<Version>,
+        new_versions: &Map<String, Version>,
+        config: &WorkspaceConfig,
+    ) -> Vec<String> {
+        let mut tags = vec![];
 
-        if !tagged.0.success() {
-            return Err(Error::NotTagged(tag.to_string(), tagged.1, tagged.2));
+        // Release branches are tagged by `cargo ws tag` once they are merged
+        if self.no_git_commit || self.no_git_tag || self.release_branch.is_some() {
+            return tags;
+        }
+
+        if !self.no_global_tag {
+            if let Some(version) = new_version {
+                tags.push(self.tag.global_tag(version));
+            }
+        }
+
+        if !(self.no_individual_tags || config.no_individual_tags.unwrap_or_default()) {
+            for (p, v) in new_versions {
+                tags.push(self.tag.individual_tag(p, v));
+            }
+        }
+
+        tags
+    }
+
+    /// Fails if any of the given tags already exists locally or on the remote
+    pub fn check_tags(&self, root: &Utf8PathBuf, tags: &[String]) -> Result<(), Error> {
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        let remote = if self.no_git_push {
+            None
+        } else {
+            Some(self.git_remote.as_str())
+        };
+
+        let existing = existing_tags(root, remote)?;
+        let conflicts = tags
+            .iter()
+            .filter(|x| existing.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !conflicts.is_empty() {
+            return Err(Error::TagsExist(conflicts.join(", ")));
         }
 
         Ok(())
@@ -298,3 +409,65 @@ impl GitOpt {
         )
     }
 }
+
+/// Returns the tags that exist locally and, if given, on the remote
+pub fn existing_tags(root: &Utf8PathBuf, remote: Option<&str>) -> Result<Vec<String>, Error> {
+    let (_, local, _) = git(root, &["tag", "--list"])?;
+    let mut existing = local.lines().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    if let Some(remote) = remote {
+        let (status, out, err) = git(root, &["ls-remote", "--tags", remote])?;
+
+        // An empty list from an unreachable remote would hide the conflicting tags
+        if !status.success() {
+            return Err(Error::RemoteTags(remote.to_string(), err));
+        }
+
+        existing.extend(out.lines().filter_map(|line| {
+            line.split('\t')
+                .nth(1)?
+                .strip_prefix("refs/tags/")
+                .map(|x| x.trim_end_matches("^{}").to_string())
+        }));
+    }
+
+    Ok(existing)
+}
+
+/// Adds the files to the git index, even if they are not tracked yet
+pub fn add_files(root: &Utf8PathBuf, files: &[Utf8PathBuf]) -> Result<(), Error> {
+    let mut args = vec!["add", "--"];
+    args.extend(files.iter().map(|x| x.as_str()));
+
+    let added = git(root, &args)?;
+
+    if !added.0.success() {
+        return Err(Error::NotAdded(added.1, added.2));
+    }
+
+    Ok(())
+}
+
+/// Creates an annotated tag on `HEAD`
+pub fn create_tag(root: &Utf8PathBuf, tag: &str, msg: &str) -> Result<(), Error> {
+    let tagged = git(root, &["tag", tag, "-m", msg])?;
+
+    if !tagged.0.success() {
+        return Err(Error::NotTagged(tag.to_string(), tagged.1, tagged.2));
+    }
+
+    Ok(())
+}
+
+/// Counts the commits in the range that touch the path
+pub fn count_commits(root: &Utf8PathBuf, range: &str, path: &str) -> Result<usize, Error> {
+    let (status, out, err) = git(root, &["rev-list", "--count", range, "--", path])?;
+
+    // A failure would otherwise look like there were no commits
+    if !status.success() {
+        return Err(Error::NotCounted(range.to_string(), err));
+    }
+
+    out.parse()
+        .map_err(|_| Error::NotCounted(range.to_string(), out.clone()))
+}
@@ -0,0 +1,175 @@
+use crate::utils::{
+    info, read_config, Error, PackageConfig, Result, WorkspaceConfig, INTERNAL_ERR,
+};
+
+use std::{fmt, process::Command};
+
+use camino::Utf8Path;
+use cargo_metadata::{Metadata, Package};
+use serde::Deserialize;
+
+/// Commands to run at the steps of a release
+#[derive(Deserialize, Default, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
+pub struct HooksConfig {
+    pub preversion: Option<String>,
+    pub postversion: Option<String>,
+    pub prepublish: Option<String>,
+    pub postpublish: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    /// Before the manifests are changed
+    PreVersion,
+    /// After the manifests are changed, before they are committed
+    PostVersion,
+    /// Before the crate is published
+    PrePublish,
+    /// After the crate is published
+    PostPublish,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PreVersion => "preversion",
+            Self::PostVersion => "postversion",
+            Self::PrePublish => "prepublish",
+            Self::PostPublish => "postpublish",
+        })
+    }
+}
+
+/// What the hook runs for, passed to it as `CARGO_WS_*` environment variables
+#[derive(Debug, Default)]
+pub struct HookEnv {
+    /// The crate, `None` for the workspace hooks
+    pub name: Option<String>,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub tag: Option<String>,
+    /// `name@version` of every crate in the release
+    pub versions: Vec<String>,
+}
+
+impl HooksConfig {
+    /// Runs the command configured for the hook, if any, with the shell in the given directory
+    pub fn run(&self, hook: Hook, dir: &Utf8Path, env: &HookEnv) -> Result {
+        let (command, mut cmd) = match self.command(hook, dir, env) {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+
+        let target = env.name.as_deref().unwrap_or("workspace");
+
+        info!(
+            "running",
+            format!("{} hook for {}: {}", hook, target, command)
+        );
+
+        if !cmd.status()?.success() {
+            return Err(Error::Hook(hook.to_string(), target.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the command configured for the hook and the shell process running it
+    fn command(&self, hook: Hook, dir: &Utf8Path, env: &HookEnv) -> Option<(&String, Command)> {
+        let command = match hook {
+            Hook::PreVersion => &self.preversion,
+            Hook::PostVersion => &self.postversion,
+            Hook::PrePublish => &self.prepublish,
+            Hook::PostPublish => &self.postpublish,
+        }
+        .as_ref()?;
+
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+
+        let mut cmd = Command::new(shell);
+
+        cmd.arg(flag)
+            .arg(command)
+            .current_dir(dir)
+            .env("CARGO_WS_HOOK", hook.to_string())
+            .env("CARGO_WS_VERSIONS", env.versions.join(" "));
+
+        for (key, value) in [
+            ("CARGO_WS_NAME", &env.name),
+            ("CARGO_WS_OLD_VERSION", &env.old_version),
+            ("CARGO_WS_NEW_VERSION", &env.new_version),
+            ("CARGO_WS_TAG", &env.tag),
+        ] {
+            if let Some(value) = value {
+                cmd.env(key, value);
+            }
+        }
+
+        Some((command, cmd))
+    }
+}
+
+/// Runs the hook configured in the workspace metadata from the workspace root
+pub fn run_workspace_hook(metadata: &Metadata, hook: Hook, env: &HookEnv) -> Result {
+    let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+
+    match config.hooks {
+        Some(hooks) => hooks.run(hook, &metadata.workspace_root, env),
+        None => Ok(()),
+    }
+}
+
+/// Runs the hook configured in the package metadata from the crate directory
+pub fn run_package_hook(pkg: &Package, hook: Hook, env: &HookEnv) -> Result {
+    let config: PackageConfig = read_config(&pkg.metadata)?;
+
+    match config.hooks {
+        Some(hooks) => hooks.run(hook, pkg.manifest_path.parent().expect(INTERNAL_ERR), env),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let hooks = HooksConfig {
+            postversion: Some("./update.sh".to_string()),
+            ..Default::default()
+        };
+
+        let env = HookEnv {
+            name: Some("a".to_string()),
+            new_version: Some("0.2.0".to_string()),
+            versions: vec!["a@0.2.0".to_string(), "b@0.3.0".to_string()],
+            ..Default::default()
+        };
+
+        let dir = Utf8Path::new(".");
+
+        assert!(hooks.command(Hook::PreVersion, dir, &env).is_none());
+
+        let (command, cmd) = hooks.command(Hook::PostVersion, dir, &env).unwrap();
+        let envs = cmd
+            .get_envs()
+            .map(|(key, value)| (key.to_str().unwrap(), value.and_then(|x| x.to_str())))
+            .collect::<Vec<_>>();
+
+        assert_eq!(command, "./update.sh");
+        assert_eq!(
+            envs,
+            [
+                ("CARGO_WS_HOOK", Some("postversion")),
+                ("CARGO_WS_NAME", Some("a")),
+                ("CARGO_WS_NEW_VERSION", Some("0.2.0")),
+                ("CARGO_WS_VERSIONS", Some("a@0.2.0 b@0.3.0")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+
+use cargo_metadata::{Metadata, Package};
+use serde::Deserialize;
+use spdx::{Expression, ParseMode};
+use url::Url;
+
+use crate::utils::{read_config, warn, Error, PackageConfig, Result, WorkspaceConfig};
+
+/// How a lint should be treated when it finds a problem
+#[derive(Deserialize, Debug, Clone, Copy, Ord, Eq, PartialOrd, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Names of all the lints, all of them are warnings by default
+pub const LINTS: &[&str] = &[
+    "missing-description",
+    "missing-license",
+    "description-too-long",
+    "invalid-url",
+    "too-many-keywords",
+    "invalid-keyword",
+    "missing-readme",
+    "invalid-category",
+    "invalid-license",
+    "missing-license-file",
+    "inconsistent-repository",
+];
+
+// Taken from https://crates.io/category_slugs
+const CATEGORIES: &[&str] = &[
+    "accessibility",
+    "aerospace",
+    "aerospace::drones",
+    "aerospace::protocols",
+    "aerospace::simulation",
+    "aerospace::space-protocols",
+    "aerospace::unmanned-aerial-vehicles",
+    "algorithms",
+    "api-bindings",
+    "asynchronous",
+    "authentication",
+    "caching",
+    "command-line-interface",
+    "command-line-utilities",
+    "compilers",
+    "compression",
+    "computer-vision",
+    "concurrency",
+    "config",
+    "cryptography",
+    "cryptography::cryptocurrencies",
+    "data-structures",
+    "database",
+    "database-implementations",
+    "date-and-time",
+    "development-tools",
+    "development-tools::build-utils",
+    "development-tools::cargo-plugins",
+    "development-tools::debugging",
+    "development-tools::ffi",
+    "development-tools::procedural-macro-helpers",
+    "development-tools::profiling",
+    "development-tools::testing",
+    "email",
+    "embedded",
+    "emulators",
+    "encoding",
+    "external-ffi-bindings",
+    "filesystem",
+    "finance",
+    "game-development",
+    "game-engines",
+    "games",
+    "graphics",
+    "gui",
+    "hardware-support",
+    "internationalization",
+    "localization",
+    "mathematics",
+    "memory-management",
+    "multimedia",
+    "multimedia::audio",
+    "multimedia::encoding",
+    "multimedia::images",
+    "multimedia::video",
+    "network-programming",
+    "no-std",
+    "no-std::no-alloc",
+    "os",
+    "os::android-apis",
+    "os::freebsd-apis",
+    "os::linux-apis",
+    "os::macos-apis",
+    "os::unix-apis",
+    "os::windows-apis",
+    "parser-implementations",
+    "parsing",
+    "rendering",
+    "rendering::data-formats",
+    "rendering::engine",
+    "rendering::graphics-api",
+    "rust-patterns",
+    "science",
+    "science::bioinformatics",
+    "science::bioinformatics::genomics",
+    "science::bioinformatics::proteomics",
+    "science::bioinformatics::sequence-analysis",
+    "science::geo",
+    "science::neuroscience",
+    "science::robotics",
+    "simulation",
+    "template-engine",
+    "text-editors",
+    "text-processing",
+    "value-formatting",
+    "virtualization",
+    "visualization",
+    "wasm",
+    "web-programming",
+    "web-programming::http-client",
+    "web-programming::http-server",
+    "web-programming::websocket",
+];
+
+/// A problem found by a lint in a crate
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub package: String,
+    pub name: &'static str,
+    pub level: LintLevel,
+    pub message: String,
+}
+
+/// Runs the lints on the given packages and returns the problems found by the
+/// lints which are not allowed. Lint levels are read from the workspace
+/// metadata and can be overridden by the package metadata.
+///
+/// The lints are simple heuristics, and if they find no problems, it does not
+/// guarantee that the crate can be published successfully.
+///
+/// Most of the lints are based on the [cargo reference recommendations][1].
+///
+/// [1]: https://doc.rust-lang.org/cargo/reference/publishing.html#before-publishing-a-new-crate
+pub fn lint(metadata: &Metadata, pkgs: &[&Package]) -> Result<Vec<Lint>> {
+    let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+    let ws_levels = config.lints.unwrap_or_default();
+
+    check_names(&ws_levels)?;
+
+    let repository = common_repository(pkgs);
+    let mut lints = vec![];
+
+    for pkg in pkgs {
+        let config: PackageConfig = read_config(&pkg.metadata)?;
+        let pkg_levels = config.lints.unwrap_or_default();
+
+        check_names(&pkg_levels)?;
+
+        let mut problems = package_problems(pkg);
+
+        if let (Some(common), Some(own)) = (&repository, &pkg.repository) {
+            if normalize_repository(own) != normalize_repository(common) {
+                problems.push((
+                    "inconsistent-repository",
+                    format!("'repository' is {} but other crates use {}", own, common),
+                ));
+            }
+        }
+
+        for (name, message) in problems {
+            let level = pkg_levels
+                .get(name)
+                .or_else(|| ws_levels.get(name))
+                .copied()
+                .unwrap_or(LintLevel::Warn);
+
+            if level != LintLevel::Allow {
+                lints.push(Lint {
+                    package: pkg.name.clone(),
+                    name,
+                    level,
+                    message,
+                });
+            }
+        }
+    }
+
+    Ok(lints)
+}
+
+/// Prints the problems found by the lints. Fails if any of them is denied, or
+/// if any of them is a warning and `strict` is true.
+pub fn report_lints(lints: &[Lint], strict: bool) -> Result {
+    let mut failed = 0;
+
+    for lint in lints {
+        let text = format!("{}: {} ({})", lint.package, lint.message, lint.name);
+
+        if lint.level == LintLevel::Deny || strict {
+            failed += 1;
+            warn!("lint denied", text);
+        } else {
+            warn!("lint", text);
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Lint(failed));
+    }
+
+    Ok(())
+}
+
+fn check_names(levels: &BTreeMap<String, LintLevel>) -> Result {
+    for name in levels.keys() {
+        if !LINTS.contains(&name.as_str()) {
+            return Err(Error::UnknownLint(name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+fn package_problems(pkg: &Package) -> Vec<(&'static str, String)> {
+    let mut problems = Vec::new();
+
+    // Mandatory fields.
+    if pkg.description.is_none() {
+        problems.push((
+            "missing-description",
+            "'description' field should be set".to_string(),
+        ));
+    }
+    if pkg.license.is_none() && pkg.license_file.is_none() {
+        problems.push((
+            "missing-license",
+            "either 'license' or 'license-file' field should be set".to_string(),
+        ));
+    }
+
+    // Too long description.
+    const MAX_DESCRIPTION_LEN: usize = 1000;
+    if pkg
+        .description
+        .as_ref()
+        .map(|d| d.len() > MAX_DESCRIPTION_LEN)
+        .unwrap_or(false)
+    {
+        problems.push((
+            "description-too-long",
+            format!(
+                "Description is too long (max {} characters)",
+                MAX_DESCRIPTION_LEN
+            ),
+        ));
+    }
+
+    // URLs must be valid.
+    validate_url(&pkg.homepage.as_deref(), "homepage", &mut problems);
+    validate_url(
+        &pkg.documentation.as_deref(),
+        "documentation",
+        &mut problems,
+    );
+    validate_url(&pkg.repository.as_deref(), "repository", &mut problems);
+
+    // Keywords limit and size
+    const MAX_KEYWORDS: usize = 5;
+    if pkg.keywords.len() > MAX_KEYWORDS {
+        problems.push((
+            "too-many-keywords",
+            format!("Too many keywords (max {} keywords)", MAX_KEYWORDS),
+        ));
+    }
+
+    const MAX_KEYWORD_LEN: usize = 20;
+    for kw in pkg.keywords.iter() {
+        if kw.len() > MAX_KEYWORD_LEN {
+            problems.push((
+                "invalid-keyword",
+                format!(
+                    "Keyword is too long (max {} characters): {}",
+                    MAX_KEYWORD_LEN, kw
+                ),
+            ));
+        } else if !valid_keyword(kw) {
+            problems.push((
+                "invalid-keyword",
+                format!("Keyword contains invalid characters: {}", kw),
+            ));
+        }
+    }
+
+    // crates.io ignores unknown categories with a warning
+    for category in pkg.categories.iter() {
+        if !CATEGORIES.contains(&category.as_str()) {
+            problems.push((
+                "invalid-category",
+                format!("Category is not a valid crates.io slug: {}", category),
+            ));
+        }
+    }
+
+    if let Some(license) = &pkg.license {
+        if Expression::parse_mode(license, CRATES_IO_LICENSE_MODE).is_err() {
+            problems.push((
+                "invalid-license",
+                format!("License is not a valid SPDX expression: {}", license),
+            ));
+        }
+    }
+
+    // Referenced files must exist
+    let dir = pkg.manifest_path.parent().unwrap_or(&pkg.manifest_path);
+
+    if let Some(readme) = &pkg.readme {
+        if !dir.join(readme).is_file() {
+            problems.push((
+                "missing-readme",
+                format!("Readme file does not exist: {}", readme),
+            ));
+        }
+    }
+
+    if let Some(license_file) = &pkg.license_file {
+        if !dir.join(license_file).is_file() {
+            problems.push((
+                "missing-license-file",
+                format!("License file does not exist: {}", license_file),
+            ));
+        }
+    }
+
+    problems
+}
+
+// Adapted from:
+// https://github.com/rust-lang/crates.io/blob/d507a12560ab923c2a1a061e5365fe6b1f1293a8/src/licenses.rs
+const CRATES_IO_LICENSE_MODE: ParseMode = ParseMode {
+    allow_lower_case_operators: false,
+    allow_slash_as_or_operator: true,
+    allow_imprecise_license_names: false,
+    allow_postfix_plus_on_gpl: true,
+};
+
+/// Returns the repository used by most of the given packages
+fn common_repository(pkgs: &[&Package]) -> Option<String> {
+    let mut counts: Vec<(String, usize)> = vec![];
+
+    for repository in pkgs.iter().filter_map(|p| p.repository.as_ref()) {
+        let normalized = normalize_repository(repository);
+
+        match counts
+            .iter_mut()
+            .find(|(r, _)| normalize_repository(r) == normalized)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((repository.clone(), 1)),
+        }
+    }
+
+    // Keep the first one when tied
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(r, _)| r)
+}
+
+fn normalize_repository(repository: &str) -> &str {
+    let repository = repository.trim_end_matches('/');
+    repository.strip_suffix(".git").unwrap_or(repository)
+}
+
+// Adapted from:
+// https://github.com/rust-lang/crates.io/blob/d507a12560ab923c2a1a061e5365fe6b1f1293a8/src/models/keyword.rs#L56
+fn valid_keyword(keyword: &str) -> bool {
+    let mut chars = keyword.chars();
+    let first = match chars.next() {
+        None => return false,
+        Some(c) => c,
+    };
+    first.is_ascii_alphanumeric()
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+')
+}
+
+// Adapted from:
+// https://github.com/rust-lang/crates.io/blob/d507a12560ab923c2a1a061e5365fe6b1f1293a8/src/controllers/krate/publish.rs#L233
+fn validate_url(url: &Option<&str>, field: &str, problems: &mut Vec<(&'static str, String)>) {
+    let Some(url) = url else {
+        return;
+    };
+
+    // Manually check the string, as `Url::parse` may normalize relative URLs
+    // making it difficult to ensure that both slashes are present.
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        problems.push((
+            "invalid-url",
+            format!("URL for field `{field}` must begin with http:// or https:// (url: {url})"),
+        ));
+    }
+
+    // Ensure the entire URL parses as well
+    if Url::parse(url).is_err() {
+        problems.push((
+            "invalid-url",
+            format!("`{field}` is not a valid url: `{url}`"),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir, write};
+
+    use cargo_metadata::MetadataCommand;
+
+    use super::*;
+
+    fn metadata(ws_metadata: &str, foo: &str, bar: &str) -> (tempfile::TempDir, Metadata) {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        write(
+            tempdir.path().join("Cargo.toml"),
+            format!(
+                r#"
+                [workspace]
+                members = ["foo", "bar"]
+
+                {}
+                "#,
+                ws_metadata
+            ),
+        )
+        .unwrap();
+
+        for (name, extra) in [("foo", foo), ("bar", bar)] {
+            create_dir(tempdir.path().join(name)).unwrap();
+            write(
+                tempdir.path().join(name).join("Cargo.toml"),
+                format!(
+                    r#"
+                    [package]
+                    name = "{}"
+                    version = "0.1.0"
+                    description = "A crate"
+                    {}
+
+                    [lib]
+                    path = "lib.rs"
+                    "#,
+                    name, extra
+                ),
+            )
+            .unwrap();
+        }
+
+        let mut cmd = MetadataCommand::new();
+
+        cmd.manifest_path(tempdir.path().join("Cargo.toml"));
+        cmd.no_deps();
+
+        (tempdir, cmd.exec().unwrap())
+    }
+
+    fn run(metadata: &Metadata) -> Vec<(String, &'static str, LintLevel)> {
+        let pkgs = metadata.packages.iter().collect::<Vec<_>>();
+
+        lint(metadata, &pkgs)
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.package, l.name, l.level))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let (_dir, metadata) = metadata(
+            "",
+            r#"
+            readme = "README.md"
+            license-file = "LICENSE"
+            categories = ["science::math"]
+            "#,
+            r#"license = "MIT/Apache-2.0""#,
+        );
+
+        assert_eq!(
+            run(&metadata),
+            vec![
+                ("foo".to_string(), "invalid-category", LintLevel::Warn),
+                ("foo".to_string(), "missing-readme", LintLevel::Warn),
+                ("foo".to_string(), "missing-license-file", LintLevel::Warn),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let (_dir, metadata) = metadata(
+            r#"
+            [workspace.metadata.workspaces.lints]
+            invalid-license = "deny"
+            missing-readme = "allow"
+            "#,
+            r#"
+            license = "Apache-2.0 MIT"
+            readme = "README.md"
+            "#,
+            r#"
+            license = "Apache-2.0 MIT"
+            readme = "README.md"
+
+            [package.metadata.workspaces.lints]
+            invalid-license = "warn"
+            missing-readme = "deny"
+            "#,
+        );
+
+        assert_eq!(
+            run(&metadata),
+            vec![
+                ("foo".to_string(), "invalid-license", LintLevel::Deny),
+                ("bar".to_string(), "invalid-license", LintLevel::Warn),
+                ("bar".to_string(), "missing-readme", LintLevel::Deny),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_repository() {
+        let (_dir, metadata) = metadata(
+            "",
+            r#"
+            license = "MIT"
+            repository = "https://github.com/foo/bar"
+            "#,
+            r#"
+            license = "MIT"
+            repository = "https://github.com/foo/baz.git"
+            "#,
+        );
+
+        assert_eq!(
+            run(&metadata),
+            vec![(
+                "bar".to_string(),
+                "inconsistent-repository",
+                LintLevel::Warn
+            )]
+        );
+    }
+
+    #[test]
+    fn test_lint_unknown() {
+        let (_dir, metadata) = metadata(
+            r#"
+            [workspace.metadata.workspaces.lints]
+            missing-readmes = "allow"
+            "#,
+            "",
+            "",
+        );
+
+        assert!(matches!(
+            lint(&metadata, &metadata.packages.iter().collect::<Vec<_>>()),
+            Err(Error::UnknownLint(_))
+        ));
+    }
+}
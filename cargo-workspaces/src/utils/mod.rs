@@ -1,30 +1,48 @@
-mod basic_checks;
+mod build;
 mod cargo;
 mod changable;
 mod config;
+mod credentials;
 mod dag;
 mod dev_dep_remover;
 mod error;
 mod git;
+mod hooks;
+mod lint;
 mod list;
+mod package;
 mod pkg;
 mod publish;
+mod publish_checks;
+mod replacement;
+mod tag;
 mod version;
 
-pub use basic_checks::basic_checks;
-pub use cargo::{cargo, cargo_config_get, change_versions, rename_packages};
+pub use build::BuildOpt;
+pub use cargo::{
+    cargo, cargo_config_get, cargo_config_path, cargo_quiet, change_lockfile, change_versions,
+    rename_packages,
+};
 pub use changable::{ChangeData, ChangeOpt};
 pub use config::{read_config, PackageConfig, WorkspaceConfig};
+pub use credentials::registry_token;
 pub use dag::dag;
-pub use dev_dep_remover::{should_remove_dev_deps, DevDependencyRemover};
+pub use dev_dep_remover::{
+    journal_dir, restore_active_dev_deps, restore_dev_deps, should_remove_dev_deps,
+    DevDependencyRemover, DevDepsMode,
+};
 pub(crate) use error::{debug, info, warn};
 pub use error::{get_debug, set_debug, Error};
-pub use git::{git, GitOpt};
+pub use git::{add_files, count_commits, create_tag, existing_tags, git, GitOpt, Worktree};
+pub use hooks::{run_package_hook, run_workspace_hook, Hook, HookEnv, HooksConfig};
+pub use lint::{lint, report_lints, LintLevel};
 pub use list::{list, ListOpt, ListPublicOpt};
+pub use package::{package_all, write_index_entry};
 pub use pkg::{get_pkgs, is_private, Pkg};
-pub use publish::{
-    create_http_client, filter_private, is_published, package_registry, RegistryOpt,
-};
+pub use publish::{filter_private, publish_failure, Indexes, PublishFailure, RegistryOpt};
+pub use publish_checks::check_publishable;
+pub use replacement::Replacement;
+pub use tag::{list_tags, Tag, TagOpt};
 pub use version::VersionOpt;
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
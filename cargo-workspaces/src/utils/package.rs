@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap as Map, BTreeSet},
+    fs::{create_dir_all, read, read_to_string, remove_dir_all, write, File, OpenOptions},
+    io::Write,
+};
+
+use crate::utils::{
+    cargo, cargo_quiet, dag, info, journal_dir, should_remove_dev_deps, BuildOpt,
+    DevDependencyRemover, DevDepsMode, Error, Indexes, Result, INTERNAL_ERR,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{DependencyKind, Metadata, Package};
+use flate2::read::GzDecoder;
+use indexmap::IndexSet as Set;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tar::Archive;
+use toml_edit::Document;
+
+/// What cargo warns about a patch that doesn't replace any dependency
+const UNUSED_PATCH: &str = "was not used in the crate graph";
+
+/// A crate whose packaged sources are used instead of the ones from the registry
+#[derive(Debug)]
+struct Patch {
+    /// Name of the registry, `None` for crates.io
+    registry: Option<String>,
+    name: String,
+    path: Utf8PathBuf,
+}
+
+/// A crate packaged by [`package_all`]
+#[derive(Debug)]
+pub struct Packaged<'a> {
+    pub pkg: &'a Package,
+    pub version: &'a String,
+    /// The `.crate` archive
+    pub file: Utf8PathBuf,
+}
+
+/// Packages the crates in the given order into the target directory. Each crate
+/// is verified against the packaged sources of the workspace crates it depends
+/// on, so they don't need to be published first.
+pub fn package_all<'a>(
+    metadata: &Metadata,
+    pkgs: &'a [(Package, String)],
+    visited: &Set<Utf8PathBuf>,
+    indexes: &Indexes,
+    target_dir: &Utf8Path,
+    dev_deps_mode: DevDepsMode,
+    build: &BuildOpt,
+) -> Result<Vec<Packaged<'a>>> {
+    let (names, _) = dag(pkgs);
+
+    if target_dir.exists() {
+        remove_dir_all(target_dir)?;
+    }
+
+    let mut sources = Map::<&str, (&Package, Utf8PathBuf)>::new();
+    let mut packaged = vec![];
+
+    for p in visited {
+        let (pkg, version) = names.get(p).expect(INTERNAL_ERR);
+
+        info!("packaging", format!("{} v{}", pkg.name, version));
+
+        // Patch the workspace crates it depends on, directly or not
+        let mut patches = vec![];
+        let mut seen = BTreeSet::new();
+        let mut queue = vec![*pkg];
+
+        while let Some(krate) = queue.pop() {
+            for dep in &krate.dependencies {
+                if dep.kind == DependencyKind::Development {
+                    continue;
+                }
+
+                if let Some((dep_pkg, path)) = sources.get(dep.name.as_str()) {
+                    if seen.insert(&dep.name) {
+                        patches.push(Patch {
+                            registry: indexes.registries(dep_pkg).remove(0),
+                            name: dep.name.clone(),
+                            path: path.clone(),
+                        });
+                        queue.push(dep_pkg);
+                    }
+                }
+            }
+        }
+
+        let dev_deps_remover = if dev_deps_mode == DevDepsMode::Keep
+            || !should_remove_dev_deps(&pkg.dependencies, pkgs)
+        {
+            None
+        } else {
+            Some(DevDependencyRemover::new(
+                p.as_std_path(),
+                &journal_dir(metadata)?,
+                metadata.workspace_root.as_std_path(),
+                dev_deps_mode,
+                pkgs,
+            )?)
+        };
+
+        let path = package(
+            &metadata.workspace_root,
+            p,
+            &pkg.name,
+            version,
+            target_dir,
+            &patches,
+            &build.args(pkg)?,
+        )?;
+
+        drop(dev_deps_remover);
+
+        packaged.push(Packaged {
+            pkg,
+            version,
+            file: Utf8PathBuf::from(format!("{}.crate", path)),
+        });
+        sources.insert(&pkg.name, (pkg, path));
+    }
+
+    Ok(packaged)
+}
+
+/// Packages the crate and verifies it by building its packaged sources with the
+/// build arguments, using the given patches for its dependencies.
+/// Returns the directory with the packaged sources of the crate.
+fn package(
+    root: &Utf8Path,
+    manifest_path: &Utf8Path,
+    name: &str,
+    version: &str,
+    target_dir: &Utf8Path,
+    patches: &[Patch],
+    build_args: &[String],
+) -> Result<Utf8PathBuf> {
+    let patches = patches
+        .iter()
+        .map(|x| {
+            format!(
+                "patch.{}.{}.path={:?}",
+                x.registry.as_deref().unwrap_or("crates-io"),
+                x.name,
+                x.path.as_str()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // The patches are needed to resolve the dependencies of the packaged crate, but
+    // cargo also applies them to the workspace, where they are never used since the
+    // crates are path dependencies there. So cargo's own verification can't tell if
+    // they were used, and the packaged sources are built separately below instead.
+    let mut args = vec![
+        "package",
+        "--no-verify",
+        "--allow-dirty",
+        "--manifest-path",
+        manifest_path.as_str(),
+        "--target-dir",
+        target_dir.as_str(),
+    ];
+
+    for patch in &patches {
+        args.push("--config");
+        args.push(patch);
+    }
+
+    let (_, stderr) = cargo_quiet(root, &args, &[])?;
+
+    // Hide the warnings about the patches not being used by the workspace
+    let mut unused = false;
+
+    for line in stderr.lines() {
+        if line.contains(UNUSED_PATCH) {
+            unused = true;
+        } else if !(unused && line.starts_with(' ')) {
+            unused = false;
+            eprintln!("{}", line);
+        }
+    }
+
+    if stderr.contains("error:") {
+        return Err(Error::Verify(name.to_string()));
+    }
+
+    let package_dir = target_dir.join("package");
+    let path = package_dir.join(format!("{}-{}", name, version));
+
+    Archive::new(GzDecoder::new(File::open(format!("{}.crate", path))?)).unpack(&package_dir)?;
+
+    let manifest_path = path.join("Cargo.toml");
+
+    // Keep the packaged crate out of any workspace above the target directory
+    OpenOptions::new()
+        .append(true)
+        .open(&manifest_path)?
+        .write_all(b"\n[workspace]\n")?;
+
+    let mut args = vec![
+        "build",
+        "--manifest-path",
+        manifest_path.as_str(),
+        "--target-dir",
+        target_dir.as_str(),
+    ];
+
+    for patch in &patches {
+        args.push("--config");
+        args.push(patch);
+    }
+
+    args.extend(build_args.iter().map(|x| x.as_str()));
+
+    let (_, stderr) = cargo(root, &args, &[])?;
+
+    if stderr.contains("error:") {
+        return Err(Error::Verify(name.to_string()));
+    }
+
+    // The packaged crate depends on the registry versions, so an unused patch
+    // means it was verified against something else than the packaged sources
+    if stderr.contains(UNUSED_PATCH) {
+        return Err(Error::UnusedPatch(name.to_string()));
+    }
+
+    Ok(path)
+}
+
+/// Adds the packaged crate to the index of a local registry, replacing the
+/// entry for the same version if there is one
+pub fn write_index_entry(index_dir: &Utf8Path, packaged: &Packaged) -> Result {
+    let path = index_dir.join(index_path(&packaged.pkg.name));
+
+    // The packaged manifest has the values inherited from the workspace
+    let manifest =
+        read_to_string(packaged.file.with_extension("").join("Cargo.toml"))?.parse::<Document>()?;
+    let rust_version = manifest
+        .get("package")
+        .and_then(|x| x.get("rust-version"))
+        .and_then(|x| x.as_str());
+
+    let entry = index_entry(
+        packaged.pkg,
+        packaged.version,
+        rust_version,
+        &read(&packaged.file)?,
+    );
+
+    let mut lines = if path.exists() {
+        read_to_string(&path)?
+            .lines()
+            .filter(|x| {
+                serde_json::from_str::<Value>(x).map_or(true, |x| x["vers"] != entry["vers"])
+            })
+            .map(|x| x.to_string())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    lines.push(entry.to_string());
+
+    create_dir_all(path.parent().expect(INTERNAL_ERR))?;
+    write(&path, lines.join("\n") + "\n")?;
+
+    Ok(())
+}
+
+fn index_entry(pkg: &Package, version: &str, rust_version: Option<&str>, archive: &[u8]) -> Value {
+    let deps = pkg
+        .dependencies
+        .iter()
+        // Dev-dependencies are never used when resolving registry crates
+        .filter(|x| x.kind != DependencyKind::Development)
+        .map(|x| {
+            json!({
+                "name": x.rename.as_ref().unwrap_or(&x.name),
+                "req": x.req.to_string(),
+                "features": x.features,
+                "optional": x.optional,
+                "default_features": x.uses_default_features,
+                "target": x.target.as_ref().map(|t| t.to_string()),
+                "kind": if x.kind == DependencyKind::Build { "build" } else { "normal" },
+                "registry": x.registry,
+                "package": x.rename.as_ref().map(|_| &x.name),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Like crates.io, the features using the newer syntax go in `features2`
+    // so that older cargo versions, which can't parse them, skip the entry
+    let (features2, features) = pkg
+        .features
+        .iter()
+        .partition::<Map<_, _>, _>(|(_, values)| {
+            values
+                .iter()
+                .any(|x| x.starts_with("dep:") || x.contains("?/"))
+        });
+
+    let mut entry = json!({
+        "name": pkg.name,
+        "vers": version,
+        "deps": deps,
+        "cksum": format!("{:x}", Sha256::digest(archive)),
+        "features": features,
+        "yanked": false,
+        "links": pkg.links,
+    });
+
+    if !features2.is_empty() {
+        entry["features2"] = json!(features2);
+        entry["v"] = json!(2);
+    }
+
+    if let Some(rust_version) = rust_version {
+        entry["rust_version"] = json!(rust_version);
+    }
+
+    entry
+}
+
+/// Path of the crate's file in the index
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("Serde_Json"), "se/rd/serde_json");
+    }
+}
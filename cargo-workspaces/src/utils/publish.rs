@@ -1,22 +1,33 @@
 //! Helper functions useful when publishing (or preparing for publishing) crates.
 
-use std::convert::TryFrom;
+use std::{
+    collections::{BTreeMap as Map, BTreeSet},
+    convert::TryFrom,
+    fmt,
+    fs::read_to_string,
+};
 
-use crate::utils::{cargo_config_get, is_private, Error, Result};
+use crate::utils::{
+    cargo_config_get, cargo_config_path, debug, is_private, registry_token, Error, Result,
+    INTERNAL_ERR,
+};
 
 use camino::Utf8PathBuf;
 use cargo_metadata::{Metadata, Package};
 use clap::Parser;
 use indexmap::IndexSet as Set;
+use oclif::console::strip_ansi_codes;
+use semver::Version;
 use tame_index::{
     external::{
         http::{HeaderMap, HeaderValue},
         reqwest::{blocking::Client, header::AUTHORIZATION, Certificate},
     },
-    index::{ComboIndex, ComboIndexCache, RemoteGitIndex, RemoteSparseIndex},
+    index::{ComboIndex, ComboIndexCache, LocalRegistry, RemoteGitIndex, RemoteSparseIndex},
     utils::flock::LockOptions,
-    IndexLocation, IndexUrl, KrateName,
+    IndexKrate, IndexLocation, IndexUrl, KrateName,
 };
+use toml_edit::Document;
 
 #[derive(Debug, Parser)]
 #[clap(next_help_heading = "REGISTRY OPTIONS")]
@@ -42,31 +53,101 @@ pub fn filter_private(visited: Set<Utf8PathBuf>, pkgs: &[(Package, String)]) ->
         .collect()
 }
 
+/// Where to look up the published versions of a crate
+#[derive(Debug)]
+pub enum RegistryIndex<'a> {
+    Index(IndexUrl<'a>),
+    /// A [directory source][1] containing the unpacked crates
+    ///
+    /// [1]: https://doc.rust-lang.org/cargo/reference/source-replacement.html#directory-sources
+    Directory(Utf8PathBuf),
+}
+
 pub fn package_registry<'a>(
     metadata: &Metadata,
     registry: Option<&'a String>,
     pkg: &Package,
-) -> Result<IndexUrl<'a>> {
-    let url = if let Some(registry) =
-        registry.or_else(|| pkg.publish.as_deref().and_then(|x| x.first()))
-    {
-        let registry_url = cargo_config_get(
-            &metadata.workspace_root,
-            &format!("registries.{}.index", registry),
-        )?;
-        IndexUrl::NonCratesIo(registry_url.into())
+) -> Result<RegistryIndex<'a>> {
+    let registry = registry.or_else(|| pkg.publish.as_deref().and_then(|x| x.first()));
+    let root = &metadata.workspace_root;
+
+    if let Some(index) = source_replacement(root, registry.map_or("crates-io", |x| x.as_str()))? {
+        return Ok(index);
+    }
+
+    let url = if let Some(registry) = registry {
+        let registry_url = cargo_config_get(root, &format!("registries.{}.index", registry))?;
+
+        match registry_url.strip_prefix("file://") {
+            // Local registries have their index in an `index` folder, otherwise
+            // it's a git index and handled like a remote one
+            Some(path) if Utf8PathBuf::from(path).join("index").is_dir() => {
+                IndexUrl::Local(Utf8PathBuf::from(path).into())
+            }
+            _ => IndexUrl::NonCratesIo(registry_url.into()),
+        }
     } else {
         IndexUrl::crates_io(None, None, None)?
     };
 
-    Ok(url)
+    Ok(RegistryIndex::Index(url))
 }
 
-pub fn create_http_client(workspace_root: &Utf8PathBuf, token: &Option<String>) -> Result<Client> {
+/// Follows the `replace-with` chain of the source with the given name to a local
+/// registry or directory source.
+fn source_replacement<'a>(root: &Utf8PathBuf, name: &str) -> Result<Option<RegistryIndex<'a>>> {
+    let mut replacement = name.to_string();
+    let mut seen = BTreeSet::new();
+
+    // Like cargo, replacements can be replaced too
+    while let Ok(next) = cargo_config_get(root, &format!("source.{}.replace-with", replacement)) {
+        if !seen.insert(next.clone()) || next == name {
+            return Err(Error::SourceCycle(name.to_string()));
+        }
+
+        replacement = next;
+    }
+
+    if replacement == name {
+        return Ok(None);
+    }
+
+    debug!("source replaced with", replacement);
+
+    if let Ok(path) = cargo_config_path(root, &format!("source.{}.local-registry", replacement)) {
+        return Ok(Some(RegistryIndex::Index(IndexUrl::Local(path.into()))));
+    }
+
+    if let Ok(path) = cargo_config_path(root, &format!("source.{}.directory", replacement)) {
+        return Ok(Some(RegistryIndex::Directory(path)));
+    }
+
+    if let Ok(url) = cargo_config_get(root, &format!("source.{}.registry", replacement)) {
+        return Ok(Some(RegistryIndex::Index(IndexUrl::NonCratesIo(
+            url.into(),
+        ))));
+    }
+
+    Ok(None)
+}
+
+fn create_http_client(
+    workspace_root: &Utf8PathBuf,
+    registry: &str,
+    token: &Option<String>,
+) -> Result<Client> {
     let client_builder = Client::builder().use_rustls_tls();
     let client_builder = if let Some(ref token) = token {
+        // Tokens come from files and commands, so they can contain anything
+        let value = HeaderValue::from_str(token).map_err(|_| {
+            Error::Credential(
+                registry.to_string(),
+                "the token contains characters not allowed in an HTTP header".to_string(),
+            )
+        })?;
+
         let mut headers = HeaderMap::new();
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(token).unwrap());
+        headers.insert(AUTHORIZATION, value);
         client_builder.default_headers(headers)
     } else {
         client_builder
@@ -82,30 +163,508 @@ pub fn create_http_client(workspace_root: &Utf8PathBuf, token: &Option<String>)
     Ok(client_builder.build()?)
 }
 
-pub fn is_published(
-    client: &Client,
-    index_url: IndexUrl,
-    name: &str,
-    version: &str,
-) -> Result<bool> {
-    let index_cache = ComboIndexCache::new(IndexLocation::new(index_url))?;
+/// The registry indexes used during a run. Each index is only opened (and
+/// fetched for git indexes) once, and the crates looked up in it are
+/// remembered.
+pub struct Indexes<'a> {
+    metadata: &'a Metadata,
+    registry: Option<&'a String>,
+    all_registries: bool,
+    token: Option<&'a String>,
+    opened: Map<Option<String>, OpenedIndex>,
+}
+
+struct OpenedIndex {
+    source: Source,
+    versions: Map<String, Vec<String>>,
+}
+
+enum Source {
+    Index(Box<ComboIndex>),
+    Directory(Utf8PathBuf),
+}
+
+impl<'a> Indexes<'a> {
+    /// When `all_registries` is true, crates are looked up in every registry
+    /// of their `publish` list instead of only the first one. Without a
+    /// `token`, the one configured in cargo for the registry is used.
+    pub fn new(
+        metadata: &'a Metadata,
+        registry: Option<&'a String>,
+        all_registries: bool,
+        token: Option<&'a String>,
+    ) -> Self {
+        Self {
+            metadata,
+            registry,
+            all_registries,
+            token,
+            opened: Map::new(),
+        }
+    }
+
+    /// Returns the registries the crate is looked up in, `None` being the
+    /// default one
+    pub fn registries(&self, pkg: &Package) -> Vec<Option<String>> {
+        match &pkg.publish {
+            Some(registries) if self.all_registries && !registries.is_empty() => {
+                registries.iter().cloned().map(Some).collect()
+            }
+            _ => vec![self
+                .registry
+                .or_else(|| pkg.publish.as_deref().and_then(|x| x.first()))
+                .cloned()],
+        }
+    }
+
+    /// Looks up the given crates in advance, concurrently for sparse indexes
+    pub fn prefetch(&mut self, pkgs: &[&Package]) -> Result {
+        let mut names = Map::<Option<String>, BTreeSet<String>>::new();
+
+        for pkg in pkgs {
+            for registry in self.registries(pkg) {
+                self.open(&registry, pkg)?;
+                names.entry(registry).or_default().insert(pkg.name.clone());
+            }
+        }
+
+        for (registry, names) in names {
+            let opened = self.opened.get_mut(&registry).expect(INTERNAL_ERR);
+
+            let sparse = match &opened.source {
+                Source::Index(index) => match index.as_ref() {
+                    ComboIndex::Sparse(sparse) => Some(sparse),
+                    _ => None,
+                },
+                Source::Directory(_) => None,
+            };
+
+            if let Some(sparse) = sparse {
+                let lock = LockOptions::cargo_package_lock(None)?.try_lock()?;
+
+                for (name, krate) in sparse.krates(names, false, &lock) {
+                    opened.versions.insert(name, versions(krate)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the crate version is published to its first registry
+    pub fn is_published(&mut self, pkg: &Package, version: &str) -> Result<bool> {
+        let registry = self.registries(pkg).swap_remove(0);
+
+        self.is_published_to(&registry, pkg, version)
+    }
+
+    /// Checks if the crate version is published to the given registry, which
+    /// should be one of [`Indexes::registries`]
+    pub fn is_published_to(
+        &mut self,
+        registry: &Option<String>,
+        pkg: &Package,
+        version: &str,
+    ) -> Result<bool> {
+        match self.versions(registry, pkg)? {
+            Some(versions) => Ok(versions.iter().any(|v| v == version)),
+            None => {
+                let dir = self.directory(registry);
+                is_in_directory(dir, &pkg.name, version)
+            }
+        }
+    }
+
+    /// Checks if any version of the crate is published to the given registry,
+    /// which should be one of [`Indexes::registries`]
+    pub fn has_crate(&mut self, registry: &Option<String>, pkg: &Package) -> Result<bool> {
+        match self.versions(registry, pkg)? {
+            Some(versions) => Ok(!versions.is_empty()),
+            None => {
+                let dir = self.directory(registry);
+                has_directory_crate(dir, &pkg.name)
+            }
+        }
+    }
+
+    /// Returns the published versions of the crate, `None` for directory sources
+    fn versions(
+        &mut self,
+        registry: &Option<String>,
+        pkg: &Package,
+    ) -> Result<Option<&Vec<String>>> {
+        self.open(registry, pkg)?;
+
+        let opened = self.opened.get_mut(registry).expect(INTERNAL_ERR);
+
+        let index = match &opened.source {
+            Source::Index(index) => index,
+            Source::Directory(_) => return Ok(None),
+        };
+
+        if !opened.versions.contains_key(&pkg.name) {
+            let lock = LockOptions::cargo_package_lock(None)?.try_lock()?;
+            let krate = index.krate(KrateName::try_from(pkg.name.as_str())?, false, &lock);
+
+            opened.versions.insert(pkg.name.clone(), versions(krate)?);
+        }
+
+        Ok(Some(&opened.versions[&pkg.name]))
+    }
+
+    /// The path of an opened directory source
+    fn directory(&self, registry: &Option<String>) -> &Utf8PathBuf {
+        match &self.opened[registry].source {
+            Source::Directory(dir) => dir,
+            Source::Index(_) => unreachable!("{}", INTERNAL_ERR),
+        }
+    }
+
+    fn open(&mut self, registry: &Option<String>, pkg: &Package) -> Result {
+        if self.opened.contains_key(registry) {
+            return Ok(());
+        }
+
+        let source = match package_registry(self.metadata, registry.as_ref(), pkg)? {
+            RegistryIndex::Index(index_url) => {
+                let root = &self.metadata.workspace_root;
+
+                // crates.io doesn't need a token for reading the index
+                let token = match (self.token, registry) {
+                    (Some(token), _) => Some(token.clone()),
+                    (None, Some(name)) if name != "crates-io" => {
+                        registry_token(root, name, index_url.as_str())?
+                    }
+                    _ => None,
+                };
+
+                let client =
+                    create_http_client(root, registry.as_deref().unwrap_or("crates-io"), &token)?;
+
+                Source::Index(Box::new(open_index(&client, index_url)?))
+            }
+            RegistryIndex::Directory(dir) => Source::Directory(dir),
+        };
+
+        self.opened.insert(
+            registry.clone(),
+            OpenedIndex {
+                source,
+                versions: Map::new(),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+fn open_index(client: &Client, index_url: IndexUrl) -> Result<ComboIndex> {
+    debug!("opening index", index_url.as_str());
+
     let lock = LockOptions::cargo_package_lock(None)?.try_lock()?;
 
-    let index: ComboIndex = match index_cache {
-        ComboIndexCache::Git(git) => {
-            let mut rgi = RemoteGitIndex::new(git, &lock)?;
+    Ok(match index_url {
+        // Validating would check the checksum of every crate in the registry
+        IndexUrl::Local(path) => LocalRegistry::open(path.into(), false)?.into(),
+        index_url => match ComboIndexCache::new(IndexLocation::new(index_url))? {
+            ComboIndexCache::Git(git) => {
+                let mut rgi = RemoteGitIndex::new(git, &lock)?;
+
+                rgi.fetch(&lock)?;
+                rgi.into()
+            }
+            ComboIndexCache::Sparse(sparse) => {
+                RemoteSparseIndex::new(sparse, client.clone()).into()
+            }
+            _ => return Err(Error::UnsupportedCratesIndexType),
+        },
+    })
+}
 
-            rgi.fetch(&lock)?;
-            rgi.into()
+fn versions(
+    krate: std::result::Result<Option<IndexKrate>, tame_index::Error>,
+) -> Result<Vec<String>> {
+    match krate {
+        Ok(Some(crate_data)) => Ok(crate_data
+            .versions
+            .into_iter()
+            .map(|v| v.version.to_string())
+            .collect()),
+        Ok(None) | Err(tame_index::Error::NoCrateVersions) => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks if the directory source has any version of the crate, see [`is_in_directory`]
+fn has_directory_crate(dir: &Utf8PathBuf, name: &str) -> Result<bool> {
+    if dir.join(name).join("Cargo.toml").is_file() {
+        return Ok(true);
+    }
+
+    let prefix = format!("{}-", name);
+
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+
+        // Make sure `foo-bar-0.1.0` is not taken for a version of `foo`
+        if entry
+            .file_name()
+            .strip_prefix(&prefix)
+            .is_some_and(|x| Version::parse(x).is_ok())
+        {
+            return Ok(true);
         }
-        ComboIndexCache::Sparse(sparse) => RemoteSparseIndex::new(sparse, client.clone()).into(),
-        _ => return Err(Error::UnsupportedCratesIndexType),
+    }
+
+    Ok(false)
+}
+
+/// Directory sources contain a folder per crate, named `<name>-<version>` when
+/// they were vendored with `--versioned-dirs`, or just `<name>` otherwise.
+fn is_in_directory(dir: &Utf8PathBuf, name: &str, version: &str) -> Result<bool> {
+    if dir.join(format!("{}-{}", name, version)).is_dir() {
+        return Ok(true);
+    }
+
+    let manifest = match read_to_string(dir.join(name).join("Cargo.toml")) {
+        Ok(manifest) => manifest,
+        Err(_) => return Ok(false),
     };
 
-    let index_crate = index.krate(KrateName::try_from(name)?, false, &lock);
-    match index_crate {
-        Ok(Some(crate_data)) => Ok(crate_data.versions.iter().any(|v| v.version == version)),
-        Ok(None) | Err(tame_index::Error::NoCrateVersions) => Ok(false),
-        Err(e) => Err(e.into()),
+    let manifest = manifest.parse::<Document>()?;
+
+    // A vendored manifest without a plain version can't be the one we look for
+    Ok(manifest
+        .get("package")
+        .and_then(|x| x.get("version"))
+        .and_then(|x| x.as_str())
+        == Some(version))
+}
+
+/// Why `cargo publish` failed, parsed from its output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishFailure {
+    RateLimited,
+    Timeout,
+    AlreadyExists,
+    Unauthorized,
+    Verification,
+    Unknown,
+}
+
+impl PublishFailure {
+    /// Whether publishing again later might succeed
+    pub fn is_transient(self) -> bool {
+        matches!(self, Self::RateLimited | Self::Timeout)
+    }
+}
+
+impl fmt::Display for PublishFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::RateLimited => "rate limited by the registry",
+            Self::Timeout => "network timeout",
+            Self::AlreadyExists => "version already exists",
+            Self::Unauthorized => "authentication failed",
+            Self::Verification => "verification failed",
+            Self::Unknown => "unknown error",
+        })
+    }
+}
+
+/// Parses the stderr of `cargo publish`, returning `None` if it succeeded
+pub fn publish_failure(stderr: &str) -> Option<PublishFailure> {
+    let stderr = strip_ansi_codes(stderr).to_lowercase();
+
+    // Compiler output can mention anything, so a failed build has to be
+    // recognized before looking for the other failures
+    if stderr.contains("failed to verify package tarball") || stderr.contains("could not compile") {
+        return Some(PublishFailure::Verification);
+    }
+
+    if stderr.contains("uploading") && !stderr.contains("error:") {
+        return None;
+    }
+
+    // Only look at the errors reported by cargo itself and their causes
+    let mut errors = vec![];
+    let mut in_cause = false;
+
+    for line in stderr.lines() {
+        if line.starts_with("error:") {
+            errors.push(line);
+            in_cause = false;
+        } else if line.starts_with("caused by:") {
+            in_cause = true;
+        } else if in_cause && line.starts_with(' ') {
+            errors.push(line);
+        } else if !line.trim().is_empty() {
+            in_cause = false;
+        }
+    }
+
+    let any = |patterns: &[&str]| {
+        errors
+            .iter()
+            .any(|line| patterns.iter().any(|x| line.contains(x)))
+    };
+
+    Some(if any(&["already exists", "is already uploaded"]) {
+        PublishFailure::AlreadyExists
+    } else if any(&["status 429", "too many requests", "published too many"]) {
+        PublishFailure::RateLimited
+    } else if any(&[
+        "status 401",
+        "status 403",
+        "unauthorized",
+        "forbidden",
+        "no token found",
+        "invalid token",
+        "cargo login",
+    ]) {
+        PublishFailure::Unauthorized
+    } else if any(&[
+        "timed out",
+        "timeout",
+        "connection reset",
+        "couldn't connect",
+        "failed to connect",
+    ]) {
+        PublishFailure::Timeout
+    } else {
+        PublishFailure::Unknown
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn test_source_replacement_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+        create_dir_all(root.join(".cargo")).unwrap();
+        write(
+            root.join(".cargo").join("config.toml"),
+            r#"
+            [source.crates-io]
+            replace-with = "mirror"
+
+            [source.mirror]
+            replace-with = "vendored"
+
+            [source.vendored]
+            local-registry = "registry"
+
+            [source.a]
+            replace-with = "b"
+
+            [source.b]
+            replace-with = "a"
+            "#,
+        )
+        .unwrap();
+
+        match source_replacement(&root, "crates-io").unwrap() {
+            Some(RegistryIndex::Index(IndexUrl::Local(path))) => {
+                assert!(path.as_str().ends_with("registry"))
+            }
+            other => panic!("unexpected replacement {:?}", other),
+        }
+
+        assert!(matches!(
+            source_replacement(&root, "a"),
+            Err(Error::SourceCycle(name)) if name == "a"
+        ));
+        assert!(source_replacement(&root, "vendored").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_is_in_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+        for (name, manifest) in [
+            (
+                "plain",
+                "[package]\nname = \"plain\"\nversion = \"0.1.0\"\n",
+            ),
+            ("no-package", "[lib]\n"),
+            (
+                "inherited",
+                "[package]\nname = \"inherited\"\nversion.workspace = true\n",
+            ),
+        ] {
+            create_dir_all(root.join(name)).unwrap();
+            write(root.join(name).join("Cargo.toml"), manifest).unwrap();
+        }
+
+        create_dir_all(root.join("versioned-0.2.0")).unwrap();
+
+        assert!(is_in_directory(&root, "plain", "0.1.0").unwrap());
+        assert!(!is_in_directory(&root, "plain", "0.2.0").unwrap());
+        assert!(!is_in_directory(&root, "no-package", "0.1.0").unwrap());
+        assert!(!is_in_directory(&root, "inherited", "0.1.0").unwrap());
+        assert!(is_in_directory(&root, "versioned", "0.2.0").unwrap());
+        assert!(!is_in_directory(&root, "missing", "0.1.0").unwrap());
+    }
+
+    #[test]
+    fn test_publish_failure() {
+        let cases = [
+            (
+                "   Packaging foo v0.1.0\n   Uploading foo v0.1.0\n    Uploaded foo v0.1.0",
+                None,
+            ),
+            (
+                "   Uploading foo v0.1.0\nerror: failed to publish to registry at https://crates.io\n\nCaused by:\n  the remote server responded with an error (status 429 Too Many Requests): You have published too many new crates in a short period of time.",
+                Some(PublishFailure::RateLimited),
+            ),
+            (
+                "   Uploading foo v0.1.0\nerror: failed to publish to registry at https://crates.io\n\nCaused by:\n  [28] Timeout was reached (Operation timed out after 30000 milliseconds)",
+                Some(PublishFailure::Timeout),
+            ),
+            (
+                "error: crate foo@0.1.0 already exists on crates.io index",
+                Some(PublishFailure::AlreadyExists),
+            ),
+            (
+                "error: no token found, please run `cargo login`",
+                Some(PublishFailure::Unauthorized),
+            ),
+            (
+                "   Uploading foo v0.1.0\nerror: failed to publish to registry at https://crates.io\n\nCaused by:\n  the remote server responded with an error (status 403 Forbidden): this token does not have the required permissions",
+                Some(PublishFailure::Unauthorized),
+            ),
+            (
+                "   Verifying foo v0.1.0\nerror: failed to verify package tarball\n\nCaused by:\n  could not compile `foo` (lib) due to 1 previous error",
+                Some(PublishFailure::Verification),
+            ),
+            (
+                "\u{1b}[1m\u{1b}[91merror\u{1b}[0m\u{1b}[1m:\u{1b}[0m something else",
+                Some(PublishFailure::Unknown),
+            ),
+        ];
+
+        for (stderr, expected) in cases {
+            assert_eq!(publish_failure(stderr), expected, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn test_publish_failure_compiler_timeout() {
+        let stderr = "   Verifying foo v0.1.0\n   Compiling foo v0.1.0\nerror[E0425]: cannot find value `timeout` in this scope\n --> src/lib.rs:1:15\n  |\n1 | const A: u8 = timeout;\n  |               ^^^^^^^ not found in this scope\n\nerror: could not compile `foo` (lib) due to 1 previous error";
+
+        assert_eq!(publish_failure(stderr), Some(PublishFailure::Verification));
+    }
+
+    #[test]
+    fn test_publish_failure_compiler_already_exists() {
+        let stderr = "   Compiling foo v0.1.0\nwarning: foo@0.1.0: output file already exists\n   Uploading foo v0.1.0\nerror: failed to publish to registry at https://crates.io\n\nCaused by:\n  the remote server responded with an error: something else";
+
+        assert_eq!(publish_failure(stderr), Some(PublishFailure::Unknown));
     }
 }
@@ -0,0 +1,196 @@
+use crate::utils::{is_private, warn, Error, Result};
+
+use cargo_metadata::{DependencyKind, Metadata};
+use semver::VersionReq;
+
+const CRATES_IO: &str = "crates-io";
+
+/// Fails if any of the public crates of the workspace can not be published.
+/// When `all_registries` is true, every registry of a crate's `publish` list is
+/// checked instead of only the first one.
+pub fn check_publishable(
+    metadata: &Metadata,
+    registry: Option<&String>,
+    all_registries: bool,
+) -> Result {
+    let problems = publish_checks(metadata, registry, all_registries);
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    for problem in &problems {
+        warn!("check failed", problem);
+    }
+
+    Err(Error::NotReady(problems.len()))
+}
+
+/// Checks the public crates of the workspace for problems that would make
+/// `cargo publish` fail halfway through publishing the workspace. Returns a
+/// list of strings, each describing a problem.
+fn publish_checks(
+    metadata: &Metadata,
+    registry: Option<&String>,
+    all_registries: bool,
+) -> Vec<String> {
+    let mut problems = vec![];
+    let no_version = VersionReq::parse("*").unwrap();
+
+    for pkg in metadata.packages.iter().filter(|p| !is_private(p)) {
+        let targets = match (registry, pkg.publish.as_deref()) {
+            (None, Some(allowed)) if all_registries && !allowed.is_empty() => {
+                allowed.iter().map(|x| x.as_str()).collect()
+            }
+            _ => vec![registry
+                .or_else(|| pkg.publish.as_deref().and_then(|x| x.first()))
+                .map_or(CRATES_IO, |x| x.as_str())],
+        };
+
+        for dep in &pkg.dependencies {
+            // dev-dependencies are stripped by cargo if they have no version
+            if !matches!(dep.kind, DependencyKind::Normal | DependencyKind::Build)
+                || dep.path.is_none()
+            {
+                continue;
+            }
+
+            let member = match metadata.packages.iter().find(|p| p.name == dep.name) {
+                Some(member) => member,
+                None => continue,
+            };
+
+            if dep.req == no_version {
+                problems.push(format!(
+                    "{} depends on {} by path without specifying a version",
+                    pkg.name, dep.name
+                ));
+            }
+
+            if is_private(member) {
+                problems.push(format!(
+                    "{} depends on {} which is private (publish = false)",
+                    pkg.name, dep.name
+                ));
+                continue;
+            }
+
+            if let Some(allowed) = &member.publish {
+                for target in &targets {
+                    if !allowed.iter().any(|x| x == target) {
+                        problems.push(format!(
+                            "{} is published to {} but its dependency {} can only be published to {}",
+                            pkg.name,
+                            target,
+                            dep.name,
+                            allowed.join(", ")
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir, write};
+
+    use cargo_metadata::MetadataCommand;
+
+    use super::*;
+
+    fn metadata(dep_toml: &str, dep_publish: &str) -> Metadata {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        write(
+            tempdir.path().join("Cargo.toml"),
+            format!(
+                r#"
+                [package]
+                name = "foo"
+                version = "0.1.0"
+
+                [lib]
+                path = "lib.rs"
+
+                [dependencies]
+                bar = {}
+
+                [workspace]
+                members = [".", "bar"]
+                "#,
+                dep_toml
+            ),
+        )
+        .unwrap();
+
+        create_dir(tempdir.path().join("bar")).unwrap();
+        write(
+            tempdir.path().join("bar").join("Cargo.toml"),
+            format!(
+                r#"
+                [package]
+                name = "bar"
+                version = "0.1.0"
+                {}
+
+                [lib]
+                path = "lib.rs"
+                "#,
+                dep_publish
+            ),
+        )
+        .unwrap();
+
+        let mut cmd = MetadataCommand::new();
+
+        cmd.manifest_path(tempdir.path().join("Cargo.toml"));
+        cmd.no_deps();
+
+        cmd.exec().unwrap()
+    }
+
+    #[test]
+    fn test_publish_checks() {
+        let metadata = metadata(r#"{ version = "0.1.0", path = "bar" }"#, "");
+
+        assert!(publish_checks(&metadata, None, false).is_empty());
+    }
+
+    #[test]
+    fn test_publish_checks_no_version() {
+        let metadata = metadata(r#"{ path = "bar" }"#, "");
+
+        assert_eq!(
+            publish_checks(&metadata, None, false),
+            vec!["foo depends on bar by path without specifying a version"]
+        );
+    }
+
+    #[test]
+    fn test_publish_checks_private() {
+        let metadata = metadata(r#"{ version = "0.1.0", path = "bar" }"#, "publish = false");
+
+        assert_eq!(
+            publish_checks(&metadata, None, false),
+            vec!["foo depends on bar which is private (publish = false)"]
+        );
+    }
+
+    #[test]
+    fn test_publish_checks_registry() {
+        let metadata = metadata(
+            r#"{ version = "0.1.0", path = "bar" }"#,
+            r#"publish = ["internal"]"#,
+        );
+
+        assert_eq!(
+            publish_checks(&metadata, None, false),
+            vec!["foo is published to crates-io but its dependency bar can only be published to internal"]
+        );
+        assert!(publish_checks(&metadata, Some(&"internal".to_string()), false).is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::utils::{Error, Result};
+
+use regex::{escape, Regex};
+use semver::Version;
+use serde::Deserialize;
+
+/// Replaces a version string in a file of the crate when versioning
+#[derive(Deserialize, Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
+pub struct Replacement {
+    /// Path relative to the crate directory
+    pub file: String,
+    /// Regex to search for
+    pub search: String,
+    /// Replacement for the matches, which can refer to the capture groups of the search
+    pub replace: String,
+}
+
+impl Replacement {
+    /// Replaces all the matches of the search in the contents after filling in the
+    /// `{{crate}}`, `{{version}}` and `{{old_version}}` placeholders. Fails if there
+    /// is no match so that stale replacements don't go unnoticed.
+    pub fn apply(
+        &self,
+        contents: &str,
+        name: &str,
+        old_version: &Version,
+        new_version: &Version,
+    ) -> Result<String> {
+        let fill = |template: &str, escape: fn(&str) -> String| {
+            template
+                .replace("{{crate}}", &escape(name))
+                .replace("{{version}}", &escape(&new_version.to_string()))
+                .replace("{{old_version}}", &escape(&old_version.to_string()))
+        };
+
+        let search = Regex::new(&fill(&self.search, escape))?;
+
+        if !search.is_match(contents) {
+            return Err(Error::Replacement(self.file.clone(), self.search.clone()));
+        }
+
+        Ok(search
+            .replace_all(contents, fill(&self.replace, str::to_string).as_str())
+            .into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn replacement(search: &str, replace: &str) -> Replacement {
+        Replacement {
+            file: "README.md".to_string(),
+            search: search.to_string(),
+            replace: replace.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let r = replacement(r#"{{crate}} = "[0-9.]+""#, r#"{{crate}} = "{{version}}""#);
+        let contents = "[dependencies]\nfoo-bar = \"0.1.0\"\nfoo = \"0.1.0\"\n";
+
+        assert_eq!(
+            r.apply(
+                contents,
+                "foo-bar",
+                &Version::parse("0.1.0").unwrap(),
+                &Version::parse("0.2.0").unwrap()
+            )
+            .unwrap(),
+            "[dependencies]\nfoo-bar = \"0.2.0\"\nfoo = \"0.1.0\"\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_captures() {
+        let r = replacement(
+            r#"(html_root_url = "https://docs.rs/{{crate}}/){{old_version}}"#,
+            "${1}{{version}}",
+        );
+
+        assert_eq!(
+            r.apply(
+                "#![doc(html_root_url = \"https://docs.rs/foo/0.1.0\")]",
+                "foo",
+                &Version::parse("0.1.0").unwrap(),
+                &Version::parse("0.1.1").unwrap()
+            )
+            .unwrap(),
+            "#![doc(html_root_url = \"https://docs.rs/foo/0.1.1\")]"
+        );
+    }
+
+    #[test]
+    fn test_apply_no_match() {
+        let r = replacement("{{crate}} = {{old_version}}", "{{crate}} = {{version}}");
+
+        assert!(matches!(
+            r.apply(
+                "foo = 0.1",
+                "foo",
+                &Version::parse("0.1.0").unwrap(),
+                &Version::parse("0.2.0").unwrap()
+            ),
+            Err(Error::Replacement(..))
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::utils::{git, validate_value_containing_name, Result};
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use semver::Version;
+use serde::Serialize;
+
+#[derive(Debug, Parser)]
+pub struct TagOpt {
+    /// Customize tag prefix (can be empty)
+    #[clap(long, default_value = "v", value_name = "PREFIX")]
+    pub tag_prefix: String,
+
+    /// Customize prefix for individual tags (should contain `%n`)
+    #[clap(
+        long,
+        default_value = "%n@",
+        value_name = "PREFIX",
+        validator = validate_value_containing_name,
+        forbid_empty_values(true)
+    )]
+    pub individual_tag_prefix: String,
+}
+
+/// A git tag that marks a release of a crate
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    #[serde(rename = "tag")]
+    pub name: String,
+    pub version: Version,
+    pub commit: String,
+    pub date: String,
+}
+
+impl TagOpt {
+    pub fn global_tag(&self, version: &Version) -> String {
+        format!("{}{}", self.tag_prefix, version)
+    }
+
+    pub fn individual_tag(&self, name: &str, version: &Version) -> String {
+        format!(
+            "{}{}",
+            self.individual_tag_prefix.replace("%n", name),
+            version
+        )
+    }
+
+    /// Returns the release tags of the given crate sorted by version. Global tags
+    /// are only considered if `global` is true and there is no individual tag for
+    /// the same version.
+    pub fn releases(&self, tags: &[Tag], name: &str, global: bool) -> Vec<Tag> {
+        let individual_prefix = self.individual_tag_prefix.replace("%n", name);
+
+        let mut releases = tags
+            .iter()
+            .filter_map(|t| parse_tag(t, &individual_prefix))
+            .collect::<Vec<_>>();
+
+        if global {
+            for tag in tags.iter().filter_map(|t| parse_tag(t, &self.tag_prefix)) {
+                if !releases.iter().any(|r| r.version == tag.version) {
+                    releases.push(tag);
+                }
+            }
+        }
+
+        releases.sort_by(|a, b| a.version.cmp(&b.version));
+        releases
+    }
+}
+
+fn parse_tag(tag: &Tag, prefix: &str) -> Option<Tag> {
+    let version = Version::parse(tag.name.strip_prefix(prefix)?).ok()?;
+
+    Some(Tag {
+        version,
+        ..tag.clone()
+    })
+}
+
+/// Lists all the tags in the repository. The version of the returned tags is
+/// meaningless until they are passed through [`TagOpt::releases`].
+pub fn list_tags(root: &Utf8PathBuf) -> Result<Vec<Tag>> {
+    let (_, out, _) = git(
+        root,
+        &[
+            "for-each-ref",
+            "refs/tags",
+            "--format=%(refname:strip=2)%09%(objectname)%09%(*objectname)%09%(creatordate:short)",
+        ],
+    )?;
+
+    Ok(out
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            let name = fields.next()?;
+            let object = fields.next()?;
+            let peeled = fields.next()?;
+            let date = fields.next()?;
+
+            // Annotated tags point to a tag object, so use the commit it points to
+            let commit = if peeled.is_empty() { object } else { peeled };
+
+            Some(Tag {
+                name: name.to_string(),
+                version: Version::new(0, 0, 0),
+                commit: commit.to_string(),
+                date: date.to_string(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tag(name: &str) -> Tag {
+        Tag {
+            name: name.to_string(),
+            version: Version::new(0, 0, 0),
+            commit: "abc".to_string(),
+            date: "2024-01-01".to_string(),
+        }
+    }
+
+    fn opt() -> TagOpt {
+        TagOpt {
+            tag_prefix: "v".to_string(),
+            individual_tag_prefix: "%n@".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_releases() {
+        let tags = vec![
+            tag("v0.2.0"),
+            tag("foo@0.1.0"),
+            tag("bar@0.3.0"),
+            tag("foo@0.2.0"),
+            tag("random"),
+        ];
+
+        let releases = opt().releases(&tags, "foo", true);
+
+        assert_eq!(
+            releases.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            vec!["foo@0.1.0", "foo@0.2.0"]
+        );
+    }
+
+    #[test]
+    fn test_releases_global() {
+        let tags = vec![tag("v0.2.0"), tag("foo@0.1.0"), tag("v0.3.0")];
+
+        let releases = opt().releases(&tags, "foo", true);
+
+        assert_eq!(
+            releases.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            vec!["foo@0.1.0", "v0.2.0", "v0.3.0"]
+        );
+        assert_eq!(releases[2].version, Version::new(0, 3, 0));
+
+        let releases = opt().releases(&tags, "foo", false);
+
+        assert_eq!(
+            releases.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            vec!["foo@0.1.0"]
+        );
+    }
+}
@@ -1,19 +1,28 @@
 use crate::utils::{
-    cargo, change_versions, info, read_config, ChangeData, ChangeOpt, Error, GitOpt, Pkg, Result,
-    WorkspaceConfig, INTERNAL_ERR,
+    add_files, cargo, change_lockfile, change_versions, git, info, read_config, run_package_hook,
+    run_workspace_hook, warn, ChangeData, ChangeOpt, Error, GitOpt, Hook, HookEnv, PackageConfig,
+    Pkg, Result, WorkspaceConfig, INTERNAL_ERR,
 };
 
+use camino::Utf8PathBuf;
 use cargo_metadata::Metadata;
 use clap::{ArgEnum, Parser};
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use dunce::canonicalize;
+use globset::{Glob, GlobMatcher};
+use indexmap::IndexMap;
 use oclif::{
     console::Style,
     term::{TERM_ERR, TERM_OUT},
 };
 use semver::{Identifier, Version};
+use similar::TextDiff;
 
 use std::{collections::BTreeMap as Map, fs, process::exit};
 
+/// The new common version (if any) and the new versions of the crates
+type NewVersions = (Option<Version>, Map<String, Version>);
+
 #[derive(Debug, Clone, ArgEnum)]
 pub enum Bump {
     Major,
@@ -55,6 +64,24 @@ pub struct VersionOpt {
     #[clap(required_if_eq("bump", "custom"), help_heading = "VERSION ARGS")]
     pub custom: Option<Version>,
 
+    /// Use the bump for the independent crates matched by glob (can be repeated)
+    #[clap(
+        long,
+        value_name = "PATTERN=BUMP",
+        multiple_occurrences = true,
+        parse(try_from_str = parse_crate_bump)
+    )]
+    pub bump_crate: Vec<(GlobMatcher, Bump)>,
+
+    /// Use the custom version for the independent crates matched by glob (can be repeated)
+    #[clap(
+        long,
+        value_name = "PATTERN=VERSION",
+        multiple_occurrences = true,
+        parse(try_from_str = parse_crate_custom)
+    )]
+    pub custom_crate: Vec<(GlobMatcher, Version)>,
+
     /// Specify prerelease identifier
     #[clap(long, value_name = "IDENTIFIER", forbid_empty_values(true))]
     pub pre_id: Option<String>,
@@ -79,9 +106,242 @@ pub struct VersionOpt {
 }
 
 impl VersionOpt {
-    pub fn do_versioning(&self, metadata: &Metadata) -> Result<Map<String, Version>> {
+    /// Changes the versions and commits them. The check runs on the new versions once
+    /// the manifests are changed, before anything is committed, and its failure undoes
+    /// the versioning like the failure of any other step.
+    pub fn do_versioning(
+        &self,
+        metadata: &Metadata,
+        check: impl FnOnce(&Map<String, Version>) -> Result,
+    ) -> Result<Map<String, Version>> {
         let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
         let branch = self.git.validate(&metadata.workspace_root, &config)?;
+
+        let (new_version, new_versions) = match self.get_versions(metadata, true)? {
+            Some(versions) => versions,
+            None => return Ok(Map::new()),
+        };
+
+        // Find out about conflicting tags before anything is written
+        self.git.check_tags(
+            &metadata.workspace_root,
+            &self.git.tags(&new_version, &new_versions, &config),
+        )?;
+
+        let root = &metadata.workspace_root;
+        let lockfile = root.join("Cargo.lock");
+
+        // Remember the state before anything is changed, by us or by the hooks, so
+        // that a failed release can be undone
+        let mut snapshot = Snapshot {
+            files: vec![(lockfile.clone(), fs::read_to_string(&lockfile).ok())],
+            head: None,
+            stash: None,
+            tags: vec![],
+            branch: None,
+        };
+
+        if !self.git.no_git_commit {
+            snapshot.head = Some(git(root, &["rev-parse", "HEAD"])?.1);
+
+            // Records the uncommitted changes to tracked files without touching them
+            let stashed = git(root, &["stash", "create"])?;
+
+            if !stashed.0.success() {
+                return Err(Error::NotStashed(stashed.2));
+            }
+
+            snapshot.stash = Some(stashed.1).filter(|x| !x.is_empty());
+        }
+
+        let release_branch = self.git.release_branch(root, &new_version)?;
+
+        let result = (|| {
+            self.run_hooks(metadata, Hook::PreVersion, &new_version, &new_versions)?;
+
+            let files = self.changed_manifests(metadata, &new_version, &new_versions)?;
+            let paths = files.keys().cloned().collect::<Vec<_>>();
+
+            snapshot.files.extend(
+                files
+                    .iter()
+                    .map(|(path, (contents, _))| (path.clone(), Some(contents.clone()))),
+            );
+
+            for (path, (_, contents)) in files {
+                fs::write(path, contents)?;
+            }
+
+            let output = cargo(root, &["update", "-w"], &[])?;
+
+            if output.1.contains("error:") {
+                return Err(Error::Update);
+            }
+
+            // Anything the hooks change in the tracked files is committed too
+            self.run_hooks(metadata, Hook::PostVersion, &new_version, &new_versions)?;
+
+            check(&new_versions)?;
+
+            let branch = match release_branch {
+                Some(release_branch) => {
+                    let created = git(root, &["checkout", "-b", &release_branch])?;
+
+                    if !created.0.success() {
+                        return Err(Error::NotCheckedOut(release_branch, created.1, created.2));
+                    }
+
+                    snapshot.branch = Some((branch.expect(INTERNAL_ERR), release_branch.clone()));
+                    Some(release_branch)
+                }
+                None => branch,
+            };
+
+            // The files changed by the replacements might not be tracked yet
+            if !self.git.no_git_commit {
+                add_files(root, &paths)?;
+            }
+
+            self.git.commit(
+                root,
+                &new_version,
+                &new_versions,
+                branch,
+                &config,
+                &mut snapshot.tags,
+            )
+        })();
+
+        if let Err(err) = result {
+            warn!("version failed", "restoring the previous state");
+            return Err(snapshot.restore(root, err));
+        }
+
+        Ok(new_versions)
+    }
+
+    /// Runs the hook of the workspace and of every crate getting a new version, the
+    /// workspace hook runs first before the change and last after it
+    fn run_hooks(
+        &self,
+        metadata: &Metadata,
+        hook: Hook,
+        new_version: &Option<Version>,
+        new_versions: &Map<String, Version>,
+    ) -> Result {
+        let package = |name: &str| {
+            metadata
+                .packages
+                .iter()
+                .find(|x| x.name == name)
+                .expect(INTERNAL_ERR)
+        };
+
+        let workspace_env = HookEnv {
+            name: None,
+            old_version: new_version.as_ref().and_then(|version| {
+                new_versions
+                    .iter()
+                    .filter(|(_, v)| v == &version)
+                    .map(|(name, _)| &package(name).version)
+                    .max()
+                    .map(|x| x.to_string())
+            }),
+            new_version: new_version.as_ref().map(|x| x.to_string()),
+            tag: new_version.as_ref().map(|x| self.git.tag.global_tag(x)),
+            versions: new_versions
+                .iter()
+                .map(|(name, version)| format!("{}@{}", name, version))
+                .collect(),
+        };
+
+        if let Hook::PreVersion = hook {
+            run_workspace_hook(metadata, hook, &workspace_env)?;
+        }
+
+        for (name, version) in new_versions {
+            let pkg = package(name);
+
+            run_package_hook(
+                pkg,
+                hook,
+                &HookEnv {
+                    name: Some(name.clone()),
+                    old_version: Some(pkg.version.to_string()),
+                    new_version: Some(version.to_string()),
+                    tag: Some(self.git.tag.individual_tag(name, version)),
+                    versions: workspace_env.versions.clone(),
+                },
+            )?;
+        }
+
+        if let Hook::PostVersion = hook {
+            run_workspace_hook(metadata, hook, &workspace_env)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the changes versioning would make without touching the tree or git
+    pub fn preview_versioning(&self, metadata: &Metadata) -> Result {
+        let config: WorkspaceConfig = read_config(&metadata.workspace_metadata)?;
+
+        let (new_version, new_versions) = match self.get_versions(metadata, false)? {
+            Some(versions) => versions,
+            None => return Ok(()),
+        };
+
+        let mut files = self.changed_manifests(metadata, &new_version, &new_versions)?;
+        let lockfile = metadata.workspace_root.join("Cargo.lock");
+
+        if lockfile.exists() {
+            let contents = fs::read_to_string(&lockfile)?;
+            let new_contents = change_lockfile(contents.clone(), &new_versions)?;
+
+            files.insert(lockfile, (contents, new_contents));
+        }
+
+        for (path, (contents, new_contents)) in &files {
+            let path = path
+                .strip_prefix(&metadata.workspace_root)
+                .unwrap_or(path.as_path());
+
+            TERM_OUT.write_str(
+                &TextDiff::from_lines(contents, new_contents)
+                    .unified_diff()
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+                    .to_string(),
+            )?;
+        }
+
+        if !self.git.no_git_commit {
+            let msg = self
+                .git
+                .commit_message(&new_version, &new_versions)
+                .unwrap_or_else(|| "(amend the last commit)".to_string());
+
+            TERM_OUT.write_line(&format!("\nCommit message:\n\n{}", msg))?;
+
+            if let Some(branch) = self
+                .git
+                .release_branch(&metadata.workspace_root, &new_version)?
+            {
+                TERM_OUT.write_line(&format!("\nRelease branch:\n\n{}", branch))?;
+            }
+
+            let tags = self.git.tags(&new_version, &new_versions, &config);
+
+            if !tags.is_empty() {
+                TERM_OUT.write_line(&format!("\nTags:\n\n{}", tags.join("\n")))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prompts for the new versions of the changed crates, returns `Ok(None)` if
+    /// there is nothing to version
+    fn get_versions(&self, metadata: &Metadata, confirm: bool) -> Result<Option<NewVersions>> {
         let mut since = self.change.since.clone();
 
         if self.change.since.is_none() {
@@ -89,7 +349,7 @@ impl VersionOpt {
 
             if self.change.force.is_none() && change_data.count == "0" && !change_data.dirty {
                 TERM_OUT.write_line("Current HEAD is already released, skipping versioning")?;
-                return Ok(Map::new());
+                return Ok(None);
             }
 
             since = change_data.since;
@@ -100,9 +360,11 @@ impl VersionOpt {
 
         if changed_p.is_empty() {
             TERM_OUT.write_line("No changes detected, skipping versioning")?;
-            return Ok(Map::new());
+            return Ok(None);
         }
 
+        self.check_crate_patterns(metadata, &changed_p, &unchanged_p)?;
+
         let mut new_version = None;
         let mut new_versions = vec![];
 
@@ -130,7 +392,19 @@ impl VersionOpt {
             unchanged_p = pkgs.1;
         }
 
-        let new_versions = self.confirm_versions(new_versions)?;
+        let new_versions = self.confirm_versions(new_versions, confirm)?;
+
+        Ok(Some((new_version, new_versions)))
+    }
+
+    /// Returns the original and the new contents of the manifests affected by the new versions
+    fn changed_manifests(
+        &self,
+        metadata: &Metadata,
+        new_version: &Option<Version>,
+        new_versions: &Map<String, Version>,
+    ) -> Result<IndexMap<Utf8PathBuf, (String, String)>> {
+        let mut files = IndexMap::new();
 
         for p in &metadata.packages {
             if !new_versions.contains_key(&p.name)
@@ -141,55 +415,72 @@ impl VersionOpt {
                 continue;
             }
 
-            fs::write(
-                &p.manifest_path,
-                format!(
-                    "{}\n",
-                    change_versions(
-                        fs::read_to_string(&p.manifest_path)?,
-                        &p.name,
-                        &new_versions,
-                        self.exact,
-                    )?
-                ),
-            )?;
+            let contents = fs::read_to_string(&p.manifest_path)?;
+            let new_contents = format!(
+                "{}\n",
+                change_versions(contents.clone(), &p.name, new_versions, self.exact)?
+            );
+
+            files.insert(p.manifest_path.clone(), (contents, new_contents));
         }
 
-        if let Some(new_version) = &new_version {
+        if let Some(new_version) = new_version {
             let workspace_root = metadata.workspace_root.join("Cargo.toml");
             let mut new_versions = new_versions.clone();
 
             new_versions.insert("".to_string(), new_version.clone());
 
-            fs::write(
-                &workspace_root,
-                format!(
-                    "{}\n",
-                    change_versions(
-                        fs::read_to_string(&workspace_root)?,
-                        "",
-                        &new_versions,
-                        self.exact
-                    )?
-                ),
-            )?;
-        }
+            // The root manifest might also be a package which was already changed
+            let (contents, new_contents) = match files.get(&workspace_root) {
+                Some((contents, new_contents)) => (contents.clone(), new_contents.clone()),
+                None => {
+                    let contents = fs::read_to_string(&workspace_root)?;
+                    (contents.clone(), contents)
+                }
+            };
 
-        let output = cargo(&metadata.workspace_root, &["update", "-w"], &[])?;
+            let new_contents = format!(
+                "{}\n",
+                change_versions(new_contents, "", &new_versions, self.exact)?
+            );
 
-        if output.1.contains("error:") {
-            return Err(Error::Update);
+            files.insert(workspace_root, (contents, new_contents));
         }
 
-        self.git.commit(
-            &metadata.workspace_root,
-            &new_version,
-            &new_versions,
-            branch,
-            &config,
-        )?;
+        for p in &metadata.packages {
+            let new_version = match new_versions.get(&p.name) {
+                Some(new_version) => new_version,
+                None => continue,
+            };
 
-        Ok(new_versions)
+            let config: PackageConfig = read_config(&p.metadata)?;
+            let dir = p.manifest_path.parent().expect(INTERNAL_ERR);
+
+            for replacement in config.replacements.unwrap_or_default() {
+                // The same file might be reached through different relative paths
+                let path = canonicalize(dir.join(&replacement.file))
+                    .ok()
+                    .and_then(|x| Utf8PathBuf::from_path_buf(x).ok())
+                    .ok_or_else(|| {
+                        Error::ReplacementNotFound(dir.join(&replacement.file).to_string())
+                    })?;
+
+                // Several replacements might target the same file, or a changed manifest
+                let (contents, current) = match files.get(&path) {
+                    Some((contents, new_contents)) => (contents.clone(), new_contents.clone()),
+                    None => {
+                        let contents = fs::read_to_string(&path)?;
+                        (contents.clone(), contents)
+                    }
+                };
+
+                let new_contents = replacement.apply(&current, &p.name, &p.version, new_version)?;
+
+                files.insert(path, (contents, new_contents));
+            }
+        }
+
+        Ok(files)
     }
 
     fn get_new_versions(
@@ -244,6 +535,7 @@ impl VersionOpt {
     fn confirm_versions(
         &self,
         versions: Vec<(String, Version, Version)>,
+        confirm: bool,
     ) -> Result<Map<String, Version>> {
         let mut new_versions = Map::new();
         let style = Style::new().for_stderr();
@@ -264,6 +556,7 @@ impl VersionOpt {
         TERM_ERR.flush()?;
 
         let create = self.yes
+            || !confirm
             || Confirm::with_theme(&ColorfulTheme::default())
                 .with_prompt("Are you sure you want to create these versions?")
                 .default(false)
@@ -296,7 +589,13 @@ impl VersionOpt {
 
         let theme = ColorfulTheme::default();
 
-        let selected = if let Some(bump) = &self.bump {
+        // The crate overrides take precedence over the bump for all the crates
+        let (bump, custom) = match pkg_name.and_then(|name| self.crate_bump(name)) {
+            Some((bump, custom)) => (Some(bump), custom),
+            None => (self.bump.clone(), self.custom.clone()),
+        };
+
+        let selected = if let Some(bump) = &bump {
             bump.selected()
         } else {
             Select::with_theme(&theme)
@@ -328,7 +627,7 @@ impl VersionOpt {
 
             inc_preid(cur_version, Identifier::AlphaNumeric(preid))
         } else if selected == 8 {
-            if let Some(version) = &self.custom {
+            if let Some(version) = custom {
                 version.clone()
             } else {
                 Input::with_theme(&theme)
@@ -346,6 +645,202 @@ impl VersionOpt {
 
         Ok(Some(new_version))
     }
+
+    /// Returns the bump given for the crate on the command line, the first matching
+    /// `--custom-crate` wins over the first matching `--bump-crate`
+    fn crate_bump(&self, name: &str) -> Option<(Bump, Option<Version>)> {
+        self.custom_crate
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(name))
+            .map(|(_, version)| (Bump::Custom, Some(version.clone())))
+            .or_else(|| {
+                self.bump_crate
+                    .iter()
+                    .find(|(pattern, _)| pattern.is_match(name))
+                    .map(|(_, bump)| (bump.clone(), None))
+            })
+    }
+
+    /// Fails if a `--bump-crate` or `--custom-crate` pattern doesn't match any of
+    /// the independent crates that can be asked about, since it would be silently
+    /// ignored otherwise. Those are the changed crates and the crates depending on
+    /// them, which get a new version when the requirement no longer matches.
+    fn check_crate_patterns(
+        &self,
+        metadata: &Metadata,
+        changed: &[Pkg],
+        unchanged: &[Pkg],
+    ) -> Result {
+        let patterns = self
+            .bump_crate
+            .iter()
+            .map(|(pattern, _)| pattern)
+            .chain(self.custom_crate.iter().map(|(pattern, _)| pattern))
+            .collect::<Vec<_>>();
+
+        if patterns.is_empty() {
+            return Ok(());
+        }
+
+        let mut asked = changed.iter().map(|p| &p.name).collect::<Vec<_>>();
+        let mut rest = unchanged.iter().collect::<Vec<_>>();
+
+        loop {
+            let (dependents, others) = rest.into_iter().partition::<Vec<_>, _>(|p| {
+                metadata
+                    .packages
+                    .iter()
+                    .find(|x| x.name == p.name)
+                    .expect(INTERNAL_ERR)
+                    .dependencies
+                    .iter()
+                    .any(|x| asked.contains(&&x.name))
+            });
+
+            if dependents.is_empty() {
+                break;
+            }
+
+            asked.extend(dependents.into_iter().map(|p| &p.name));
+            rest = others;
+        }
+
+        let independent = changed
+            .iter()
+            .chain(unchanged)
+            .filter(|p| asked.contains(&&p.name) && p.config.independent.unwrap_or(false))
+            .map(|p| &p.name)
+            .collect::<Vec<_>>();
+
+        for pattern in patterns {
+            if !independent.iter().any(|name| pattern.is_match(name)) {
+                return Err(Error::NoMatchingIndependent(
+                    pattern.glob().glob().to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_crate_bump(value: &str) -> std::result::Result<(GlobMatcher, Bump), String> {
+    let (pattern, bump) = parse_crate_value(value)?;
+
+    match Bump::from_str(bump, true)? {
+        Bump::Custom => Err("use --custom-crate for custom versions\n".to_string()),
+        bump => Ok((pattern, bump)),
+    }
+}
+
+fn parse_crate_custom(value: &str) -> std::result::Result<(GlobMatcher, Version), String> {
+    let (pattern, version) = parse_crate_value(value)?;
+
+    Ok((
+        pattern,
+        Version::parse(version).map_err(|e| format!("{}\n", e))?,
+    ))
+}
+
+/// Splits `PATTERN=VALUE` into the compiled glob and the value
+fn parse_crate_value(value: &str) -> std::result::Result<(GlobMatcher, &str), String> {
+    let (pattern, value) = value
+        .split_once('=')
+        .ok_or_else(|| "must be in the form PATTERN=VALUE\n".to_string())?;
+
+    let pattern = Glob::new(pattern).map_err(|e| format!("{}\n", e))?;
+
+    Ok((pattern.compile_matcher(), value))
+}
+
+/// The state of the files and git before versioning
+struct Snapshot {
+    files: Vec<(Utf8PathBuf, Option<String>)>,
+    head: Option<String>,
+    /// The commit made by `git stash create` with the uncommitted changes to tracked files
+    stash: Option<String>,
+    tags: Vec<String>,
+    /// The original branch and the release branch created from it
+    branch: Option<(String, String)>,
+}
+
+impl Snapshot {
+    /// Restores the previous state after the error, returning the error, or one
+    /// that also lists everything that was left behind if restoring failed
+    fn restore(self, root: &Utf8PathBuf, error: Error) -> Error {
+        // Keep going when a step fails so that as much as possible is restored
+        let mut remains = vec![];
+
+        let mut run = |args: &[&str], remains_msg: &dyn Fn(String) -> String| match git(root, args)
+        {
+            Ok((status, _, _)) if status.success() => true,
+            Ok((_, _, err)) => {
+                remains.push(remains_msg(err));
+                false
+            }
+            Err(err) => {
+                remains.push(remains_msg(err.to_string()));
+                false
+            }
+        };
+
+        for tag in &self.tags {
+            run(&["tag", "-d", tag], &|err| {
+                format!("tag {} still exists ({})", tag, err)
+            });
+        }
+
+        if let Some(head) = &self.head {
+            // Drops the commit and every change to tracked files, including the hooks' ones
+            let reset = run(&["reset", "--quiet", "--hard", head], &|err| {
+                format!(
+                    "the changes are still there instead of the state at {} ({})",
+                    head, err
+                )
+            });
+
+            if let Some((original, release)) = &self.branch {
+                if run(&["checkout", "--quiet", original], &|err| {
+                    format!(
+                        "branch {} is still checked out instead of {} ({})",
+                        release, original, err
+                    )
+                }) {
+                    run(&["branch", "-D", release], &|err| {
+                        format!("branch {} still exists ({})", release, err)
+                    });
+                }
+            }
+
+            if let (true, Some(stash)) = (reset, &self.stash) {
+                run(&["stash", "apply", "--quiet", "--index", stash], &|err| {
+                    format!(
+                        "the uncommitted changes were not reapplied, they are in {} ({})",
+                        stash, err
+                    )
+                });
+            }
+        }
+
+        // Also covers the files that are not tracked, like an ignored `Cargo.lock`
+        for (path, contents) in self.files {
+            let restored = match contents {
+                Some(contents) => fs::write(&path, contents),
+                None if path.exists() => fs::remove_file(&path),
+                None => Ok(()),
+            };
+
+            if let Err(err) = restored {
+                remains.push(format!("{} is still changed ({})", path, err));
+            }
+        }
+
+        if remains.is_empty() {
+            error
+        } else {
+            Error::NotRestored(error.to_string(), remains.join(", "))
+        }
+    }
 }
 
 fn inc_pre(pre: &[Identifier], preid: &Option<String>) -> Vec<Identifier> {
@@ -482,6 +977,29 @@ fn version_items(cur_version: &Version, preid: &Option<String>) -> Vec<(String,
 mod test_super {
     use super::*;
 
+    #[test]
+    fn test_parse_crate_bump() {
+        let (pattern, bump) = parse_crate_bump("bar-*=major").unwrap();
+
+        assert!(pattern.is_match("bar-baz"));
+        assert!(!pattern.is_match("foo"));
+        assert!(matches!(bump, Bump::Major));
+
+        assert!(parse_crate_bump("foo=custom").is_err());
+        assert!(parse_crate_bump("foo=huge").is_err());
+        assert!(parse_crate_bump("foo").is_err());
+    }
+
+    #[test]
+    fn test_parse_crate_custom() {
+        let (pattern, version) = parse_crate_custom("foo=2.0.0").unwrap();
+
+        assert!(pattern.is_match("foo"));
+        assert_eq!(version, Version::parse("2.0.0").unwrap());
+
+        assert!(parse_crate_custom("foo=two").is_err());
+    }
+
     #[test]
     fn test_inc_patch() {
         let v = inc_patch(Version::parse("0.7.2").unwrap());
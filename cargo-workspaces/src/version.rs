@@ -5,13 +5,33 @@ use clap::Parser;
 /// Bump version of crates
 #[derive(Debug, Parser)]
 pub struct Version {
+    /// Print the changes as a diff without writing anything
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Commit to a new branch and push it without tags (`%v` is replaced by the version)
+    #[clap(
+        long,
+        value_name = "NAME",
+        help_heading = "GIT OPTIONS",
+        conflicts_with_all = &["amend", "no-git-commit"],
+        forbid_empty_values(true)
+    )]
+    release_branch: Option<String>,
+
     #[clap(flatten)]
     version: VersionOpt,
 }
 
 impl Version {
-    pub fn run(self, metadata: Metadata) -> Result {
-        self.version.do_versioning(&metadata)?;
+    pub fn run(mut self, metadata: Metadata) -> Result {
+        self.version.git.release_branch = self.release_branch.take();
+
+        if self.dry_run {
+            return self.version.preview_versioning(&metadata);
+        }
+
+        self.version.do_versioning(&metadata, |_| Ok(()))?;
 
         info!("success", "ok");
         Ok(())
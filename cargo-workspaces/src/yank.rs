@@ -0,0 +1,153 @@
+use crate::utils::{
+    cargo, dag, filter_private, info, list_tags, Error, Indexes, RegistryOpt, Result, TagOpt,
+    INTERNAL_ERR,
+};
+
+use cargo_metadata::Metadata;
+use clap::Parser;
+use semver::Version;
+
+/// Yank a version of the crates from the registry
+#[derive(Debug, Parser)]
+pub struct Yank {
+    /// The version to yank
+    #[clap(
+        long,
+        required_unless_present = "from-tag",
+        conflicts_with = "from-tag"
+    )]
+    version: Option<Version>,
+
+    /// Yank the version released by this git tag, only for the crate it belongs
+    /// to if it's an individual tag
+    #[clap(long, value_name = "TAG")]
+    from_tag: Option<String>,
+
+    /// Undo a yank, putting the version back into the index
+    #[clap(long)]
+    undo: bool,
+
+    /// Show what would be yanked without doing it
+    #[clap(long)]
+    dry_run: bool,
+
+    #[clap(flatten)]
+    tag: TagOpt,
+
+    #[clap(flatten)]
+    registry: RegistryOpt,
+}
+
+impl Yank {
+    pub fn run(self, metadata: Metadata) -> Result {
+        let (version, only) = match (&self.version, &self.from_tag) {
+            (Some(version), _) => (version.clone(), None),
+            (None, Some(tag)) => self.parse_tag(&metadata, tag)?,
+            (None, None) => unreachable!("{}", INTERNAL_ERR),
+        };
+
+        let version_str = version.to_string();
+
+        let pkgs = metadata
+            .packages
+            .iter()
+            .filter(|p| only.as_ref().is_none_or(|name| &p.name == name))
+            .map(|x| (x.clone(), version_str.clone()))
+            .collect::<Vec<_>>();
+
+        let (names, visited) = dag(&pkgs);
+
+        // Filter out private packages
+        let visited = filter_private(visited, &pkgs);
+
+        let mut indexes = Indexes::new(
+            &metadata,
+            self.registry.registry.as_ref(),
+            false,
+            self.registry.token.as_ref(),
+        );
+
+        indexes.prefetch(
+            &visited
+                .iter()
+                .map(|p| names.get(p).expect(INTERNAL_ERR).0)
+                .collect::<Vec<_>>(),
+        )?;
+
+        let action = if self.undo { "unyank" } else { "yank" };
+
+        // Yank dependents before their dependencies
+        for p in visited.iter().rev() {
+            let (pkg, _) = names.get(p).expect(INTERNAL_ERR);
+            let name_ver = format!("{} v{}", pkg.name, version);
+
+            if !indexes.is_published(pkg, &version_str)? {
+                info!("not published", name_ver);
+                continue;
+            }
+
+            if self.dry_run {
+                info!("would", format!("{} {}", action, name_ver));
+                continue;
+            }
+
+            let mut args = vec!["yank", "--version", &version_str];
+
+            if self.undo {
+                args.push("--undo");
+            }
+
+            let registry = indexes.registries(pkg).remove(0);
+
+            if let Some(ref registry) = registry {
+                args.push("--registry");
+                args.push(registry);
+            }
+
+            if let Some(ref token) = self.registry.token {
+                args.push("--token");
+                args.push(token);
+            }
+
+            args.push(&pkg.name);
+
+            let (_, stderr) = cargo(&metadata.workspace_root, &args, &[])?;
+
+            if stderr.contains("error:") {
+                return Err(Error::Yank(pkg.name.clone()));
+            }
+
+            if self.undo {
+                info!("unyanked", name_ver);
+            } else {
+                info!("yanked", name_ver);
+            }
+        }
+
+        info!("success", "ok");
+        Ok(())
+    }
+
+    /// Returns the version of the tag, and the crate it belongs to if it's an
+    /// individual tag
+    fn parse_tag(&self, metadata: &Metadata, tag: &str) -> Result<(Version, Option<String>)> {
+        let tags = list_tags(&metadata.workspace_root)?;
+
+        if !tags.iter().any(|t| t.name == tag) {
+            return Err(Error::TagNotFound(tag.to_string()));
+        }
+
+        for pkg in &metadata.packages {
+            let releases = self.tag.releases(&tags, &pkg.name, false);
+
+            if let Some(release) = releases.into_iter().find(|t| t.name == tag) {
+                return Ok((release.version, Some(pkg.name.clone())));
+            }
+        }
+
+        tag.strip_prefix(&self.tag.tag_prefix)
+            .and_then(|x| Version::parse(x).ok())
+            .map(|version| (version, None))
+            .ok_or_else(|| Error::TagNotFound(tag.to_string()))
+    }
+}
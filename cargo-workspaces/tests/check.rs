@@ -0,0 +1,8 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_normal() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "check"]);
+    assert_snapshot!(err);
+}
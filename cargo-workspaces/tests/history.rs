@@ -0,0 +1,45 @@
+mod utils;
+use insta::assert_snapshot;
+use tempfile::TempDir;
+
+/// Releases 0.1.0 of both crates with a global tag, then a patch of `beta` and a
+/// minor version of `alpha` with individual tags, the last ones with custom prefixes
+fn released() -> TempDir {
+    let dir = utils::git_repo(utils::WORKSPACE);
+    utils::git(dir.path(), &["tag", "-a", "v0.1.0", "-m", "v0.1.0"]);
+
+    utils::write_files(dir.path(), &[("beta/src/lib.rs", "pub fn beta() {}\n")]);
+    utils::git(dir.path(), &["commit", "-q", "-a", "-m", "change beta"]);
+    utils::git(dir.path(), &["tag", "-a", "beta@0.1.1", "-m", "beta@0.1.1"]);
+
+    utils::write_files(dir.path(), &[("alpha/src/lib.rs", "pub fn alpha() {}\n")]);
+    utils::git(dir.path(), &["commit", "-q", "-a", "-m", "change alpha"]);
+    utils::git(dir.path(), &["tag", "-a", "rel-alpha-0.2.0", "-m", "alpha"]);
+    utils::git(dir.path(), &["tag", "-a", "release-0.2.0", "-m", "0.2.0"]);
+
+    dir
+}
+
+#[test]
+fn test_history() {
+    let dir = released();
+    let out = utils::run_out(dir.path().to_str().unwrap(), &["ws", "history"]);
+    assert_snapshot!(out);
+}
+
+#[test]
+fn test_history_custom_prefixes() {
+    let dir = released();
+    let out = utils::run_out(
+        dir.path().to_str().unwrap(),
+        &[
+            "ws",
+            "history",
+            "--tag-prefix",
+            "release-",
+            "--individual-tag-prefix",
+            "rel-%n-",
+        ],
+    );
+    assert_snapshot!(out);
+}
@@ -0,0 +1,14 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_normal() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "lint"]);
+    assert_snapshot!(err);
+}
+
+#[test]
+fn test_strict() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "lint", "--strict"]);
+    assert_snapshot!(err);
+}
@@ -0,0 +1,8 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_no_action() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "owner"]);
+    assert_snapshot!(err);
+}
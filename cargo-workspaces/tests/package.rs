@@ -0,0 +1,196 @@
+mod utils;
+use serde_json::{json, Value};
+use serial_test::serial;
+use sha2::{Digest, Sha256};
+use std::fs::{read, read_to_string};
+use tempfile::tempdir;
+
+/// `top` depends on `dep2`, which is not in the registry, so it can only be
+/// verified against the packaged sources of `dep2`
+#[test]
+#[serial]
+fn test_verify_against_packaged_dependency() {
+    let out_dir = tempdir().unwrap();
+
+    let err = utils::run_err(
+        "../fixtures/local_registry",
+        &[
+            "ws",
+            "package",
+            "--out-dir",
+            out_dir.path().to_str().unwrap(),
+        ],
+    );
+
+    assert!(err.contains("info success ok"));
+    assert!(!err.contains("was not used in the crate graph"));
+
+    let compiled = err
+        .lines()
+        .filter(|x| x.trim_start().starts_with("Compiling dep2 v0.1.0 "))
+        .collect::<Vec<_>>();
+
+    assert!(!compiled.is_empty());
+    assert!(compiled.iter().all(|x| x.contains("package/dep2-0.1.0")));
+
+    for name in ["dep1", "dep2", "top"] {
+        assert!(out_dir
+            .path()
+            .join(format!("{}-0.1.0.crate", name))
+            .exists());
+    }
+}
+
+#[test]
+#[serial]
+fn test_index() {
+    let out_dir = tempdir().unwrap();
+
+    utils::run_err(
+        "../fixtures/local_registry",
+        &[
+            "ws",
+            "package",
+            "--index",
+            "--out-dir",
+            out_dir.path().to_str().unwrap(),
+        ],
+    );
+
+    let index = out_dir.path().join("index");
+
+    for (name, path) in [("dep1", "de/p1"), ("dep2", "de/p2"), ("top", "3/t")] {
+        let contents = read_to_string(index.join(path).join(name)).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+
+        let entry = serde_json::from_str::<Value>(lines[0]).unwrap();
+        let archive = read(out_dir.path().join(format!("{}-0.1.0.crate", name))).unwrap();
+
+        assert_eq!(entry["name"], name);
+        assert_eq!(entry["vers"], "0.1.0");
+        assert_eq!(entry["yanked"], false);
+        assert_eq!(entry["cksum"], format!("{:x}", Sha256::digest(&archive)));
+    }
+
+    let top = read_to_string(index.join("3/t/top")).unwrap();
+    let deps = &serde_json::from_str::<Value>(&top).unwrap()["deps"];
+
+    assert_eq!(
+        deps,
+        &json!([
+            {
+                "name": "dep",
+                "req": ">=0.1.0, <0.2.0",
+                "features": [],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": "normal",
+                "registry": null,
+                "package": "dep1",
+            },
+            {
+                "name": "dep2",
+                "req": ">=0.1.0, <0.2.0",
+                "features": [],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": "normal",
+                "registry": null,
+                "package": null,
+            },
+        ])
+    );
+}
+
+/// Features using `dep:` or `?/` go in `features2`, as crates.io does
+#[test]
+fn test_index_features2() {
+    let dir = tempdir().unwrap();
+
+    utils::write_files(
+        dir.path(),
+        &[
+            (
+                "Cargo.toml",
+                indoc::indoc! {r#"
+                    [workspace]
+                    members = ["gamma", "delta"]
+
+                    [workspace.package]
+                    rust-version = "1.60"
+                "#},
+            ),
+            (
+                "gamma/Cargo.toml",
+                indoc::indoc! {r#"
+                    [package]
+                    name = "gamma"
+                    version = "0.1.0"
+                    edition = "2018"
+                    license = "MIT"
+                    description = "gamma"
+                    rust-version.workspace = true
+
+                    [dependencies]
+                    delta = { version = "0.1.0", path = "../delta", optional = true }
+
+                    [features]
+                    plain = []
+                    extra = ["dep:delta"]
+                    weak = ["delta?/fast"]
+                "#},
+            ),
+            ("gamma/src/lib.rs", ""),
+            (
+                "delta/Cargo.toml",
+                indoc::indoc! {r#"
+                    [package]
+                    name = "delta"
+                    version = "0.1.0"
+                    edition = "2018"
+                    license = "MIT"
+                    description = "delta"
+
+                    [features]
+                    fast = []
+                "#},
+            ),
+            ("delta/src/lib.rs", ""),
+        ],
+    );
+
+    let out_dir = dir.path().join("out");
+
+    utils::run_err(
+        dir.path().to_str().unwrap(),
+        &[
+            "ws",
+            "package",
+            "--index",
+            "--out-dir",
+            out_dir.to_str().unwrap(),
+        ],
+    );
+
+    let gamma = read_to_string(out_dir.join("index/ga/mm/gamma")).unwrap();
+    let gamma = serde_json::from_str::<Value>(&gamma).unwrap();
+
+    assert_eq!(gamma["features"], json!({ "plain": [] }));
+    assert_eq!(
+        gamma["features2"],
+        json!({ "extra": ["dep:delta"], "weak": ["delta?/fast"] })
+    );
+    assert_eq!(gamma["v"], 2);
+    assert_eq!(gamma["rust_version"], "1.60");
+
+    let delta = read_to_string(out_dir.join("index/de/lt/delta")).unwrap();
+    let delta = serde_json::from_str::<Value>(&delta).unwrap();
+
+    assert_eq!(delta["features"], json!({ "fast": [] }));
+    assert!(delta.get("features2").is_none());
+    assert!(delta.get("v").is_none());
+    assert!(delta.get("rust_version").is_none());
+}
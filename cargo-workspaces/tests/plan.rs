@@ -0,0 +1,11 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_skip_published_local_registry() {
+    let out = utils::run_out(
+        "../fixtures/local_registry",
+        &["ws", "plan", "--skip-published"],
+    );
+    assert_snapshot!(out);
+}
@@ -0,0 +1,47 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_from_tag_not_found() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "publish", "--from-tag", "missing-tag"],
+    );
+    assert_snapshot!(err);
+}
+
+#[test]
+fn test_all_registries_with_token() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "publish", "--all-registries", "--token", "secret"],
+    );
+    assert_snapshot!(err);
+}
+
+#[test]
+fn test_package_with_versioning() {
+    let err = utils::run_err("../fixtures/normal", &["ws", "publish", "--package", "top"]);
+    assert_snapshot!(err);
+}
+
+#[test]
+fn test_lint_only_published() {
+    let (_, err) = utils::run(
+        "../fixtures/local_registry",
+        &["ws", "publish", "--dry-run", "--package", "dep2"],
+    );
+
+    let lints = err
+        .lines()
+        .filter(|x| x.starts_with("warn lint"))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        lints,
+        [
+            "warn lint dep2: 'description' field should be set (missing-description)",
+            "warn lint dep2: either 'license' or 'license-file' field should be set (missing-license)",
+        ]
+    );
+}
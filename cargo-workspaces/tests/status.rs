@@ -0,0 +1,24 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_status() {
+    let dir = utils::git_repo(utils::WORKSPACE);
+    utils::git(dir.path(), &["tag", "-a", "v0.1.0", "-m", "v0.1.0"]);
+
+    utils::write_files(dir.path(), &[("beta/src/lib.rs", "pub fn beta() {}\n")]);
+    utils::git(dir.path(), &["commit", "-q", "-a", "-m", "change beta"]);
+
+    let out = utils::run_out(dir.path().to_str().unwrap(), &["ws", "status"]);
+    assert_snapshot!(out);
+}
+
+#[test]
+fn test_status_no_commits() {
+    let dir = tempfile::tempdir().unwrap();
+    utils::git(dir.path(), &["init", "-q"]);
+    utils::write_files(dir.path(), utils::WORKSPACE);
+
+    let err = utils::run_err(dir.path().to_str().unwrap(), &["ws", "status"]);
+    assert!(err.starts_with("error: unable to count the commits in HEAD"));
+}
@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 use assert_cmd::{cargo_bin, Command};
-use std::str::from_utf8;
+use std::{
+    fs::{create_dir_all, write},
+    path::Path,
+    process::Command as StdCommand,
+    str::from_utf8,
+};
+use tempfile::{tempdir, TempDir};
 
 pub fn run(dir: &str, args: &[&str]) -> (String, String) {
     let output = Command::new(cargo_bin!("cargo-ws"))
@@ -28,3 +34,76 @@ pub fn run_err(dir: &str, args: &[&str]) -> String {
     assert!(out.is_empty());
     err
 }
+
+/// Runs git in the directory with a fixed identity and dates, so that the
+/// commits and tags are the same on every run
+pub fn git(dir: &Path, args: &[&str]) {
+    let status = StdCommand::new("git")
+        .current_dir(dir)
+        .args([
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "-c",
+            "commit.gpgsign=false",
+            "-c",
+            "tag.gpgsign=false",
+            "-c",
+            "core.autocrlf=false",
+        ])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", "2024-01-01T00:00:00Z")
+        .env("GIT_COMMITTER_DATE", "2024-01-01T00:00:00Z")
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+}
+
+/// Writes the files, creating their directories
+pub fn write_files(dir: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = dir.join(path);
+
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+    }
+}
+
+/// Creates a git repository in a temporary directory with the files committed
+pub fn git_repo(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempdir().unwrap();
+
+    git(dir.path(), &["init", "-q"]);
+    write_files(dir.path(), files);
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "init"]);
+
+    dir
+}
+
+/// Files of a workspace with crates `alpha` and `beta` at version 0.1.0, using
+/// a local registry where only `alpha` is published
+pub const WORKSPACE: &[(&str, &str)] = &[
+    (".gitignore", "target\nCargo.lock\n"),
+    ("Cargo.toml", "[workspace]\nmembers = [\"alpha\", \"beta\"]\n"),
+    (
+        ".cargo/config.toml",
+        "[source.crates-io]\nreplace-with = \"local\"\n\n[source.local]\nlocal-registry = \"registry\"\n",
+    ),
+    (
+        "registry/index/al/ph/alpha",
+        "{\"name\":\"alpha\",\"vers\":\"0.1.0\",\"deps\":[],\"cksum\":\"0000000000000000000000000000000000000000000000000000000000000000\",\"features\":{},\"yanked\":false}\n",
+    ),
+    (
+        "alpha/Cargo.toml",
+        "[package]\nname = \"alpha\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+    ),
+    ("alpha/src/lib.rs", ""),
+    (
+        "beta/Cargo.toml",
+        "[package]\nname = \"beta\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+    ),
+    ("beta/src/lib.rs", ""),
+];
@@ -0,0 +1,138 @@
+mod utils;
+use insta::assert_snapshot;
+use std::fs::read_to_string;
+
+#[test]
+fn test_bump_crate_not_independent() {
+    let err = utils::run_err(
+        "../fixtures/normal",
+        &["ws", "version", "--dry-run", "--bump-crate", "dep*=major"],
+    );
+    assert_snapshot!(err);
+}
+
+/// Release branches are tagged once they are merged, so no tags are listed
+#[test]
+fn test_release_branch_dry_run() {
+    let dir = utils::git_repo(utils::WORKSPACE);
+
+    let (out, _) = utils::run(
+        dir.path().to_str().unwrap(),
+        &[
+            "ws",
+            "version",
+            "patch",
+            "--dry-run",
+            "--no-git-push",
+            "--release-branch",
+            "release-%v",
+        ],
+    );
+
+    assert!(out.contains("Release branch:\n\nrelease-0.1.1"));
+    assert!(!out.contains("Tags:"));
+}
+
+const REPLACED: &str = "[package]\nname = \"alpha\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[package.metadata.workspaces]\nreplacements = [{ file = \"VERSION\", search = \"{{old_version}}\", replace = \"{{version}}\" }]\n";
+
+/// Files changed by replacements are committed even if they weren't tracked
+#[test]
+fn test_replacement_untracked() {
+    let dir = utils::git_repo(utils::WORKSPACE);
+
+    utils::write_files(dir.path(), &[("alpha/Cargo.toml", REPLACED)]);
+    utils::git(dir.path(), &["commit", "-q", "-am", "replacements"]);
+    utils::write_files(dir.path(), &[("alpha/VERSION", "0.1.0\n")]);
+    utils::git(dir.path(), &["config", "user.name", "test"]);
+    utils::git(dir.path(), &["config", "user.email", "test@example.com"]);
+
+    utils::run(
+        dir.path().to_str().unwrap(),
+        &["ws", "version", "patch", "--yes", "--no-git-push"],
+    );
+
+    let output = std::process::Command::new("git")
+        .current_dir(dir.path())
+        .args(["show", "--name-only", "--format=", "HEAD"])
+        .output()
+        .unwrap();
+
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .any(|x| x == "alpha/VERSION"));
+    assert_eq!(
+        read_to_string(dir.path().join("alpha/VERSION")).unwrap(),
+        "0.1.1\n"
+    );
+}
+
+#[test]
+fn test_replacement_missing_file() {
+    let dir = utils::git_repo(utils::WORKSPACE);
+
+    utils::write_files(dir.path(), &[("alpha/Cargo.toml", REPLACED)]);
+    utils::git(dir.path(), &["commit", "-q", "-am", "replacements"]);
+
+    let (_, err) = utils::run(
+        dir.path().to_str().unwrap(),
+        &["ws", "version", "patch", "--yes", "--no-git-push"],
+    );
+
+    assert!(err.contains("error: unable to find file"));
+    assert!(err.contains("alpha/VERSION for the replacement"));
+}
+
+/// Patterns are checked against the changed crates, which are the only ones versioned
+#[test]
+fn test_bump_crate_unchanged() {
+    let independent = "\n[package.metadata.workspaces]\nindependent = true\n";
+    let alpha = "[package]\nname = \"alpha\"\nversion = \"0.1.0\"\nedition = \"2018\"\n"
+        .to_string()
+        + independent;
+    let beta = "[package]\nname = \"beta\"\nversion = \"0.1.0\"\nedition = \"2018\"\n".to_string()
+        + independent;
+
+    let dir = utils::git_repo(utils::WORKSPACE);
+
+    utils::write_files(
+        dir.path(),
+        &[("alpha/Cargo.toml", &alpha), ("beta/Cargo.toml", &beta)],
+    );
+    utils::git(dir.path(), &["commit", "-q", "-am", "independent"]);
+    utils::git(dir.path(), &["tag", "-a", "-m", "v0.1.0", "v0.1.0"]);
+    utils::write_files(dir.path(), &[("alpha/src/lib.rs", "pub fn alpha() {}\n")]);
+    utils::git(dir.path(), &["commit", "-q", "-am", "change alpha"]);
+
+    let root = dir.path().to_str().unwrap();
+
+    let (_, err) = utils::run(
+        root,
+        &[
+            "ws",
+            "version",
+            "patch",
+            "--dry-run",
+            "--yes",
+            "--bump-crate",
+            "beta=major",
+        ],
+    );
+
+    assert!(err.contains("error: no independent crate matches beta"));
+
+    let (_, err) = utils::run(
+        root,
+        &[
+            "ws",
+            "version",
+            "patch",
+            "--dry-run",
+            "--yes",
+            "--bump-crate",
+            "alpha=major",
+        ],
+    );
+
+    assert!(err.contains("alpha: 0.1.0 => 1.0.0"));
+}
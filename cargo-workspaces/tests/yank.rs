@@ -0,0 +1,11 @@
+mod utils;
+use insta::assert_snapshot;
+
+#[test]
+fn test_dry_run() {
+    let err = utils::run_err(
+        "../fixtures/local_registry",
+        &["ws", "yank", "--version", "0.1.0", "--dry-run"],
+    );
+    assert_snapshot!(err);
+}